@@ -0,0 +1,142 @@
+// Benchmarks of the style system on a synthetic document, shaped like our large generated
+// reports: many rows of cells under a few sections, styled by a few hundred rules.
+//
+// Run with `cargo run --release --example bench -- [-n ELEMENTS] [-j THREADS]`.
+
+extern crate getopts;
+extern crate pareidolia;
+
+use pareidolia::dom::{self, Node};
+use pareidolia::css::{self, Origin, Stylesheet};
+use pareidolia::media::{Device, MediaType};
+use pareidolia::layout::Dimensions;
+use pareidolia::style::{self, Matching, Traversal};
+use std::collections::HashMap;
+use std::process;
+use std::time::{Duration, Instant};
+
+const CELLS_PER_ROW: usize = 8;
+const ROWS_PER_SECTION: usize = 50;
+const RUNS: u32 = 5;
+
+fn main() {
+    let mut opts = getopts::Options::new();
+    opts.optopt("n", "elements", "Style a synthetic document of about N elements", "N");
+    opts.optopt("j", "jobs", "Compute styles on N threads", "N");
+
+    let matches = opts.parse(std::env::args().skip(1))
+        .unwrap_or_else(|e| fail(&e.to_string()));
+    let count_arg = |flag: &str, default: usize| -> usize {
+        match matches.opt_str(flag).map(|n| n.parse()) {
+            None => default,
+            Some(Ok(n)) if n > 0 => n,
+            Some(_) => fail(&format!("-{} takes a positive number", flag))
+        }
+    };
+    let elements = count_arg("n", 10_000);
+    let traversal = match count_arg("j", 1) {
+        1 => Traversal::Sequential,
+        threads => Traversal::Parallel(threads)
+    };
+
+    let document = synthetic_document(elements);
+    let stylesheets = vec![css::user_agent(), synthetic_stylesheet()];
+    let rules: usize = stylesheets.iter().map(|sheet| sheet.rules.len()).sum();
+
+    let mut viewport: Dimensions = Default::default();
+    viewport.content.width = 800.0;
+    viewport.content.height = 600.0;
+    let device = Device::new(MediaType::Screen, viewport);
+
+    println!("{} elements, {} rules", count_elements(&document), rules);
+    for &(name, matching) in &[("linear", Matching::Linear), ("indexed", Matching::Indexed)] {
+        time(&format!("Matched rules, {}", name), || {
+            style::matched_rules(&document, &stylesheets, &device, matching);
+        });
+    }
+
+    let mode = match traversal {
+        Traversal::Sequential => "sequentially".to_string(),
+        Traversal::Parallel(threads) => format!("on {} threads", threads)
+    };
+    let mut sharing = None;
+    time(&format!("Styled {}", mode), || {
+        sharing = Some(style::style_tree_with_stats(&document, &stylesheets, &device,
+                                                    traversal).1);
+    });
+    style::print_sharing(&sharing.unwrap());
+}
+
+fn fail(message: &str) -> ! {
+    eprintln!("{}", message);
+    process::exit(1);
+}
+
+// Run `f` a few times and print the best and mean times.
+fn time<F: FnMut()>(what: &str, mut f: F) {
+    let times: Vec<Duration> = (0..RUNS).map(|_| {
+        let start = Instant::now();
+        f();
+        start.elapsed()
+    }).collect();
+    let best = times.iter().min().unwrap();
+    let mean = times.iter().sum::<Duration>() / RUNS;
+    println!("{}: best {:.2} ms, mean {:.2} ms over {} runs",
+             what, millis(*best), millis(mean), RUNS);
+}
+
+fn millis(duration: Duration) -> f64 {
+    duration.as_secs() as f64 * 1000.0 + duration.subsec_nanos() as f64 / 1_000_000.0
+}
+
+fn element(tag: &str, id: Option<String>, class: String, children: Vec<Node>) -> Node {
+    let mut attrs = HashMap::new();
+    if let Some(id) = id {
+        attrs.insert("id".to_string(), id);
+    }
+    attrs.insert("class".to_string(), class);
+    dom::elem(tag.to_string(), attrs, children)
+}
+
+// html > body > div.report > section#sN > div.row > span.cell, with text in every cell.
+fn synthetic_document(elements: usize) -> Node {
+    let rows = elements / (CELLS_PER_ROW + 1) + 1;
+    let sections = (0..rows.div_ceil(ROWS_PER_SECTION)).map(|s| {
+        let first = s * ROWS_PER_SECTION;
+        let last = ::std::cmp::min(first + ROWS_PER_SECTION, rows);
+        let rows = (first..last).map(|r| {
+            let cells = (0..CELLS_PER_ROW).map(|c| {
+                element("span", None, format!("cell c{}", c),
+                        vec![dom::text(format!("{}:{}", r, c))])
+            }).collect();
+            element("div", None, format!("row r{}", r % 3), cells)
+        }).collect();
+        element("section", Some(format!("s{}", s)), "section".to_string(), rows)
+    }).collect();
+    let report = element("div", None, "report".to_string(), sections);
+    dom::elem("html".to_string(), HashMap::new(),
+              vec![dom::elem("body".to_string(), HashMap::new(), vec![report])])
+}
+
+// A mix of rules that match many elements, a few, and none at all.
+fn synthetic_stylesheet() -> Stylesheet {
+    let mut source = String::from("* { display: block; }\n span { display: inline; }\n");
+    for i in 0..50 {
+        source.push_str(&format!(".report .row.r{} .c{} {{ padding-left: {}px; }}\n",
+                                 i % 3, i % CELLS_PER_ROW, i));
+        source.push_str(&format!("#s{} .row > span {{ margin-top: {}px; }}\n", i, i));
+        source.push_str(&format!("section > div.row .cell.c{} {{ width: {}px; }}\n",
+                                 i % CELLS_PER_ROW, i));
+        source.push_str(&format!("table td.x{} {{ color: #00ff00; }}\n", i));
+        source.push_str(&format!("ul li .item{} a {{ color: #0000ff; }}\n", i));
+    }
+    css::parse(source, Origin::Author)
+}
+
+fn count_elements(node: &Node) -> usize {
+    let this = match node.node_type {
+        dom::NodeType::Element(_) => 1,
+        dom::NodeType::Text(_) => 0,
+    };
+    this + node.children.iter().map(count_elements).sum::<usize>()
+}
@@ -0,0 +1,92 @@
+// A counting Bloom filter of the tag names, ids and classes of an element's ancestors.
+//
+// Lets selector matching reject `a b` or `a > b` selectors without walking up the tree when
+// no ancestor could possibly match `a`. False positives are possible; false negatives aren't.
+
+use dom::ElementData;
+
+const KEY_BITS: u32 = 12;
+const KEY_MASK: u32 = (1 << KEY_BITS) - 1;
+const SIZE: usize = 1 << KEY_BITS;
+
+// Hashes of the three kinds of identifier are kept apart, so `div` the tag and `.div` differ.
+const TAG: u8 = b't';
+const ID: u8 = b'#';
+const CLASS: u8 = b'.';
+
+pub struct AncestorFilter {
+    // Counters saturate at 255 and are then never decremented, like Gecko's and Servo's.
+    counters: Box<[u8; SIZE]>,
+}
+
+impl AncestorFilter {
+    pub fn new() -> AncestorFilter {
+        AncestorFilter { counters: Box::new([0; SIZE]) }
+    }
+
+    /// Add an element entering the ancestor chain.
+    pub fn push(&mut self, elem: &ElementData) {
+        for_each_hash(elem, |hash| self.insert_hash(hash));
+    }
+
+    /// Remove an element previously added with `push`.
+    pub fn pop(&mut self, elem: &ElementData) {
+        for_each_hash(elem, |hash| self.remove_hash(hash));
+    }
+
+    /// False if no element in the filter has the identifier with this hash.
+    pub fn might_contain(&self, hash: u32) -> bool {
+        self.counters[first(hash)] != 0 && self.counters[second(hash)] != 0
+    }
+
+    fn insert_hash(&mut self, hash: u32) {
+        for &slot in &[first(hash), second(hash)] {
+            let counter = &mut self.counters[slot];
+            *counter = counter.saturating_add(1);
+        }
+    }
+
+    fn remove_hash(&mut self, hash: u32) {
+        for &slot in &[first(hash), second(hash)] {
+            let counter = &mut self.counters[slot];
+            if *counter != 255 {
+                *counter -= 1;
+            }
+        }
+    }
+}
+
+impl Default for AncestorFilter {
+    fn default() -> AncestorFilter {
+        AncestorFilter::new()
+    }
+}
+
+pub fn tag_hash(name: &str) -> u32 { hash(TAG, name) }
+pub fn id_hash(id: &str) -> u32 { hash(ID, id) }
+pub fn class_hash(class: &str) -> u32 { hash(CLASS, class) }
+
+fn for_each_hash<F: FnMut(u32)>(elem: &ElementData, mut f: F) {
+    f(tag_hash(&elem.tag_name));
+    if let Some(id) = elem.id() {
+        f(id_hash(id));
+    }
+    for class in elem.classes() {
+        if !class.is_empty() {
+            f(class_hash(class));
+        }
+    }
+}
+
+// 32-bit FNV-1a, seeded with the kind of identifier.
+fn hash(kind: u8, name: &str) -> u32 {
+    let mut hash: u32 = 0x811c9dc5;
+    for &byte in [kind].iter().chain(name.as_bytes()) {
+        hash ^= byte as u32;
+        hash = hash.wrapping_mul(0x01000193);
+    }
+    hash
+}
+
+fn first(hash: u32) -> usize { (hash & KEY_MASK) as usize }
+fn second(hash: u32) -> usize { ((hash >> KEY_BITS) & KEY_MASK) as usize }
@@ -1,39 +1,222 @@
+use properties;
+use std::cmp::Reverse;
+use media::{MediaQueryList, MediaQuery, MediaFeature, MediaType, Range, Orientation};
+use media::ColorScheme;
+
 pub struct Stylesheet {
     pub rules: Vec<Rule>,
+    pub origin: Origin,
+    // The `@import` rules, which the loader replaces with the rules they refer to.
+    pub imports: Vec<Import>,
+    pub font_faces: Vec<FontFaceRule>,
+}
+
+// `@import url(<url>) <media-query-list>;`
+pub struct Import {
+    pub url: String,
+    pub media: MediaQueryList,
+}
+
+// An `@font-face` rule with the descriptors used to select and load the font.
+pub struct FontFaceRule {
+    pub family: String,
+    pub sources: Vec<String>, // the `url()` sources, in order of preference
+    pub weight: u16,
+    pub italic: bool,
+}
+
+// Where a stylesheet comes from, which decides its precedence in the cascade.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Origin {
+    UserAgent,
+    User,
+    Author,
 }
 
 pub struct Rule {
     pub selectors: Vec<Selector>,
     pub declarations: Vec<Declaration>,
+    // The queries of the enclosing `@media` blocks, which must all match for the rule to apply.
+    pub media: Vec<MediaQueryList>,
 }
 
 pub enum Selector {
     Simple(SimpleSelector),
+    // `left <combinator> right`, where `right` must match the element itself.
+    Complex(Box<Selector>, Combinator, SimpleSelector),
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Combinator {
+    Descendant,        // `a b`
+    Child,             // `a > b`
+    NextSibling,       // `a + b`
+    SubsequentSibling, // `a ~ b`
 }
 
 pub struct SimpleSelector {
-    pub tag_name: Option<String>,
+    pub tag_name: Option<String>, // `None` for the universal selector `*`
     pub id: Option<String>,
     pub class: Vec<String>,
+    pub attributes: Vec<AttrSelector>,
+    pub pseudo_classes: Vec<PseudoClass>,
+    pub pseudo_element: Option<PseudoElement>, // only allowed on the rightmost compound selector
+}
+
+// An attribute selector such as `[lang|=en]` or `[href$=".pdf" i]`.
+pub struct AttrSelector {
+    pub name: String,
+    pub operator: AttrOperator,
+    pub case_insensitive: bool,
+}
+
+pub enum AttrOperator {
+    Exists,            // `[attr]`
+    Equals(String),    // `[attr=val]`
+    Includes(String),  // `[attr~=val]`
+    DashMatch(String), // `[attr|=val]`
+    Prefix(String),    // `[attr^=val]`
+    Suffix(String),    // `[attr$=val]`
+    Substring(String), // `[attr*=val]`
+}
+
+pub enum PseudoClass {
+    Root,
+    Empty,
+    FirstChild,
+    LastChild,
+    OnlyChild,
+    FirstOfType,
+    LastOfType,
+    OnlyOfType,
+    NthChild(Nth),
+    NthLastChild(Nth),
+    NthOfType(Nth),
+    NthLastOfType(Nth),
+    Not(Vec<Selector>),
+    Is(Vec<Selector>),
+    Where(Vec<Selector>),
+}
+
+// A part of the `content` property's value.
+#[derive(Clone, PartialEq)]
+pub enum ContentItem {
+    Text(String),
+    Attr(String),                     // `attr(name)`
+    Counter(String, String),          // `counter(name, style)`
+    Counters(String, String, String), // `counters(name, separator, style)`
+}
+
+// A part of a grid track list.
+#[derive(Clone, PartialEq)]
+pub enum Track {
+    Names(Vec<String>),              // `[name ...]`
+    Size(Value, Value),              // the min and max sizing functions, equal unless `minmax()`
+    Repeat(Repetitions, Vec<Track>), // `repeat(count, tracks)`
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Repetitions {
+    Count(u32),
+    AutoFill,
+    AutoFit,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum PseudoElement {
+    Before,
+    After,
+}
+
+// The `An+B` argument of the `:nth-*` pseudo-classes.
+#[derive(Clone, Copy, PartialEq)]
+pub struct Nth {
+    pub a: i32,
+    pub b: i32,
 }
 
 pub struct Declaration {
     pub name: String,
     pub value: Value,
+    pub important: bool,
 }
 
 #[derive(Clone, PartialEq)]
 pub enum Value {
     Keyword(String),
     Length(f32, Unit),
+    Number(f32),
     ColorValue(Color),
+    // The text of a custom property, or of a value containing `var()` references, kept unparsed
+    // until substitution at computed-value time. For a longhand set through a shorthand, the
+    // text is the shorthand's and the shorthand's name is kept along with it.
+    Unparsed(String, Option<String>),
+    // A `calc()`, `min()`, `max()` or `clamp()` expression. Once computed, only expressions
+    // involving percentages remain; they are evaluated during layout.
+    Calc(Box<Calc>),
+    // A quoted string.
+    Str(String),
+    // The parts of a `content` value, after the `attr()`, `counter()` and `counters()`
+    // functions have been parsed.
+    Content(Vec<ContentItem>),
+    // The counter names and integers of `counter-reset` or `counter-increment`.
+    Counters(Vec<(String, i32)>),
+    // A grid track list, after the `minmax()` and `repeat()` functions have been parsed.
+    Tracks(Vec<Track>),
+    // The rows of cell names of `grid-template-areas`, where `.` is an unnamed cell.
+    Areas(Vec<Vec<String>>),
+    // `span <integer>`, in the grid placement properties.
+    Span(u32),
+    // Two values that go together, like the horizontal and vertical spacing of `border-spacing`.
+    Pair(Box<Value>, Box<Value>),
+    // The functions of a `transform` list, kept as written. Transforms aren't applied, but a box
+    // with one is painted as a stacking context.
+    Transform(Vec<String>),
     // insert more values here
 }
 
+#[derive(Clone, PartialEq)]
+pub enum Calc {
+    Leaf(Value), // a number, length or percentage
+    Sum(Box<Calc>, Box<Calc>),
+    Difference(Box<Calc>, Box<Calc>),
+    Product(Box<Calc>, Box<Calc>),
+    Quotient(Box<Calc>, Box<Calc>),
+    Min(Vec<Calc>),
+    Max(Vec<Calc>),
+    Clamp(Box<Calc>, Box<Calc>, Box<Calc>), // min, value, max
+}
+
+// The type of a math expression, used to reject invalid combinations like `1px + 2`.
+#[derive(Clone, Copy, PartialEq)]
+pub enum CalcType {
+    Number,
+    Length,
+    Percentage,
+    LengthPercentage,
+}
+
 #[derive(Clone, PartialEq)]
 pub enum Unit {
+    // Absolute lengths
     Px,
-    // insert more units here
+    Pt,
+    Pc,
+    Cm,
+    Mm,
+    In,
+    // Font-relative lengths
+    Em,
+    Rem,
+    // Viewport-percentage lengths
+    Vw,
+    Vh,
+    Vmin,
+    Vmax,
+    // Relative to the containing block, resolved during layout
+    Percent,
+    // A share of the leftover space in a grid container
+    Fr,
 }
 
 #[derive(Clone, Copy, PartialEq)]
@@ -46,14 +229,64 @@ pub struct Color {
 
 pub type Specificity = (usize, usize, usize);
 
+/// The reference sizes that font- and viewport-relative lengths are resolved against.
+#[derive(Clone, Copy)]
+pub struct LengthContext {
+    pub font_size: f32,
+    pub root_font_size: f32,
+    pub viewport_width: f32,
+    pub viewport_height: f32,
+}
+
 impl Selector {
     pub fn specificity(&self) -> Specificity {
         // http://www.w3.org/TR/selectors/#specificity
-        let Selector::Simple(ref simple) = *self;
-        let a = simple.id.iter().count();
-        let b = simple.class.len();
-        let c = simple.tag_name.iter().count();
-        (a, b, c)
+        match *self {
+            Selector::Simple(ref simple) => simple.specificity(),
+            Selector::Complex(ref left, _, ref right) => {
+                let (a1, b1, c1) = left.specificity();
+                let (a2, b2, c2) = right.specificity();
+                (a1 + a2, b1 + b2, c1 + c2)
+            }
+        }
+    }
+}
+
+impl SimpleSelector {
+    pub fn specificity(&self) -> Specificity {
+        let a = self.id.iter().count();
+        let b = self.class.len() + self.attributes.len();
+        let c = self.tag_name.iter().count() + self.pseudo_element.iter().count();
+        self.pseudo_classes.iter().fold((a, b, c), |(a, b, c), pseudo| {
+            let (a2, b2, c2) = pseudo.specificity();
+            (a + a2, b + b2, c + c2)
+        })
+    }
+}
+
+impl PseudoClass {
+    pub fn specificity(&self) -> Specificity {
+        match *self {
+            // `:is()` and `:not()` take the specificity of their most specific argument.
+            PseudoClass::Not(ref selectors) | PseudoClass::Is(ref selectors) => {
+                selectors.iter().map(|s| s.specificity()).max().unwrap_or((0, 0, 0))
+            }
+            // `:where()` never adds any specificity.
+            PseudoClass::Where(_) => (0, 0, 0),
+            _ => (0, 1, 0)
+        }
+    }
+}
+
+impl Nth {
+    /// Whether the 1-based `index` of an element is of the form `An+B` for some `n >= 0`.
+    pub fn matches(&self, index: usize) -> bool {
+        let offset = index as i32 - self.b;
+        if self.a == 0 {
+            offset == 0
+        } else {
+            offset % self.a == 0 && offset / self.a >= 0
+        }
     }
 }
 
@@ -63,66 +296,1774 @@ pub fn example() -> Stylesheet {
     html_selects.push(Selector::Simple(SimpleSelector {
         tag_name: Some(String::from("html")),
         id: None,
-        class: Vec::new()}));
+        class: Vec::new(),
+        attributes: Vec::new(),
+        pseudo_classes: Vec::new(),
+        pseudo_element: None}));
     let mut html_decls: Vec<Declaration> = Vec::new();
-    html_decls.push(Declaration {
-        name: String::from("background"),
-        value: Value::ColorValue(Color {r: 255, g: 255, b: 255, a: 255}) });
+    html_decls.extend(properties::expand("background",
+        vec![Value::ColorValue(Color {r: 255, g: 255, b: 255, a: 255})], false));
 
     // div main rules
     let mut main_selects: Vec<Selector> = Vec::new();
     main_selects.push(Selector::Simple(SimpleSelector {
         tag_name: Some(String::from("div")),
         id: Some(String::from("main")),
-        class: Vec::new()}));
+        class: Vec::new(),
+        attributes: Vec::new(),
+        pseudo_classes: Vec::new(),
+        pseudo_element: None}));
     let mut main_decls: Vec<Declaration> = Vec::new();
     main_decls.push(Declaration {
         name: String::from("color"),
-        value: Value::ColorValue(Color {r: 0, g: 0, b: 255, a: 255}) });
+        value: Value::ColorValue(Color {r: 0, g: 0, b: 255, a: 255}),
+        important: false });
     main_decls.push(Declaration {
         name: String::from("height"),
-        value: Value::Length (400.0, Unit::Px) });
-    main_decls.push(Declaration {
-        name: String::from("border-width"),
-        value: Value::Length (10.0, Unit::Px) });
-    main_decls.push(Declaration {
-        name: String::from("border-color"),
-        value: Value::ColorValue(Color {r: 255, g: 255, b: 126, a: 255}) });
+        value: Value::Length (400.0, Unit::Px),
+        important: false });
+    main_decls.extend(properties::expand("border-width",
+        vec![Value::Length (10.0, Unit::Px)], false));
+    main_decls.extend(properties::expand("border-color",
+        vec![Value::ColorValue(Color {r: 255, g: 255, b: 126, a: 255})], false));
 
     // div second rules
     let mut second_selects: Vec<Selector> = Vec::new();
     second_selects.push(Selector::Simple(SimpleSelector {
         tag_name: Some(String::from("div")),
         id: Some(String::from("second")),
-        class: Vec::new()}));
+        class: Vec::new(),
+        attributes: Vec::new(),
+        pseudo_classes: Vec::new(),
+        pseudo_element: None}));
     let mut second_decls: Vec<Declaration> = Vec::new();
     second_decls.push(Declaration {
         name: String::from("color"),
-        value: Value::ColorValue(Color {r: 255, g: 0, b: 0, a: 255}) });
+        value: Value::ColorValue(Color {r: 255, g: 0, b: 0, a: 255}),
+        important: false });
     second_decls.push(Declaration {
         name: String::from("height"),
-        value: Value::Length (250.0, Unit::Px) });
-    second_decls.push(Declaration {
-        name: String::from("border-width"),
-        value: Value::Length (10.0, Unit::Px) });
-    second_decls.push(Declaration {
-        name: String::from("border-color"),
-        value: Value::ColorValue(Color {r: 126, g: 255, b: 255, a: 255}) });
+        value: Value::Length (250.0, Unit::Px),
+        important: false });
+    second_decls.extend(properties::expand("border-width",
+        vec![Value::Length (10.0, Unit::Px)], false));
+    second_decls.extend(properties::expand("border-color",
+        vec![Value::ColorValue(Color {r: 126, g: 255, b: 255, a: 255})], false));
 
     // Create the stylesheet from the rules
     let mut rules: Vec<Rule> = Vec::new();
-    rules.push(Rule { selectors: html_selects, declarations: html_decls });
-    rules.push(Rule { selectors: main_selects, declarations: main_decls });
-    rules.push(Rule { selectors: second_selects, declarations: second_decls });
-    Stylesheet { rules: rules }
+    rules.push(Rule { selectors: html_selects, declarations: html_decls, media: Vec::new() });
+    rules.push(Rule { selectors: main_selects, declarations: main_decls, media: Vec::new() });
+    rules.push(Rule { selectors: second_selects, declarations: second_decls, media: Vec::new() });
+    Stylesheet {
+        rules,
+        origin: Origin::Author,
+        imports: Vec::new(),
+        font_faces: Vec::new(),
+    }
 }
 
 impl Value {
-    /// Return the size of a length in px, or zero for non-lengths.
+    /// Return the size of an absolute length in px, or zero for non-lengths and relative lengths.
     pub fn to_px(&self) -> f32 {
         match *self {
             Value::Length(f, Unit::Px) => f,
+            Value::Length(f, Unit::In) => f * 96.0,
+            Value::Length(f, Unit::Cm) => f * 96.0 / 2.54,
+            Value::Length(f, Unit::Mm) => f * 96.0 / 25.4,
+            Value::Length(f, Unit::Pt) => f * 96.0 / 72.0,
+            Value::Length(f, Unit::Pc) => f * 16.0,
             _ => 0.0
         }
     }
+
+    /// Return the size of a length in px, resolving percentages against `reference`.
+    pub fn to_px_of(&self, reference: f32) -> f32 {
+        match *self {
+            Value::Length(f, Unit::Percent) => f * reference / 100.0,
+            Value::Calc(ref calc) => calc.evaluate(reference),
+            _ => self.to_px()
+        }
+    }
+
+    /// Whether the value passes type checking. Only math expressions can fail.
+    pub fn is_valid(&self) -> bool {
+        match *self {
+            Value::Calc(ref calc) => calc.calc_type().is_some(),
+            _ => true
+        }
+    }
+
+    /// Convert any length other than a percentage to px.
+    ///
+    /// Percentages depend on the containing block, so they are left for layout to resolve.
+    pub fn resolve(&self, ctx: &LengthContext) -> Value {
+        let px = match *self {
+            Value::Length(f, Unit::Em) => f * ctx.font_size,
+            Value::Length(f, Unit::Rem) => f * ctx.root_font_size,
+            Value::Length(f, Unit::Vw) => f * ctx.viewport_width / 100.0,
+            Value::Length(f, Unit::Vh) => f * ctx.viewport_height / 100.0,
+            Value::Length(f, Unit::Vmin) =>
+                f * ctx.viewport_width.min(ctx.viewport_height) / 100.0,
+            Value::Length(f, Unit::Vmax) =>
+                f * ctx.viewport_width.max(ctx.viewport_height) / 100.0,
+            Value::Length(_, Unit::Percent) | Value::Length(_, Unit::Fr) => return self.clone(),
+            Value::Length(..) => self.to_px(),
+            Value::Calc(ref calc) => return calc.resolve(ctx),
+            Value::Tracks(ref tracks) => {
+                return Value::Tracks(tracks.iter().map(|track| track.resolve(ctx)).collect())
+            }
+            Value::Pair(ref a, ref b) => {
+                return Value::Pair(Box::new(a.resolve(ctx)), Box::new(b.resolve(ctx)))
+            }
+            _ => return self.clone()
+        };
+        Value::Length(px, Unit::Px)
+    }
+}
+
+impl Track {
+    fn resolve(&self, ctx: &LengthContext) -> Track {
+        match *self {
+            Track::Names(_) => self.clone(),
+            Track::Size(ref min, ref max) => Track::Size(min.resolve(ctx), max.resolve(ctx)),
+            Track::Repeat(count, ref tracks) => {
+                Track::Repeat(count, tracks.iter().map(|track| track.resolve(ctx)).collect())
+            }
+        }
+    }
+}
+
+impl Calc {
+    /// The type of the expression, or `None` if it mixes types that can't be combined.
+    pub fn calc_type(&self) -> Option<CalcType> {
+        match *self {
+            Calc::Leaf(Value::Number(_)) => Some(CalcType::Number),
+            Calc::Leaf(Value::Length(_, Unit::Percent)) => Some(CalcType::Percentage),
+            Calc::Leaf(Value::Length(..)) => Some(CalcType::Length),
+            Calc::Leaf(_) => None,
+            Calc::Sum(ref a, ref b) | Calc::Difference(ref a, ref b) => {
+                add_types(a.calc_type()?, b.calc_type()?)
+            }
+            Calc::Product(ref a, ref b) => match (a.calc_type()?, b.calc_type()?) {
+                (CalcType::Number, t) | (t, CalcType::Number) => Some(t),
+                _ => None
+            },
+            Calc::Quotient(ref a, ref b) => match (a.calc_type()?, b.calc_type()?) {
+                (t, CalcType::Number) => Some(t),
+                _ => None
+            },
+            Calc::Min(ref args) | Calc::Max(ref args) => {
+                let mut args = args.iter();
+                let first = args.next()?.calc_type()?;
+                args.try_fold(first, |t, arg| add_types(t, arg.calc_type()?))
+            }
+            Calc::Clamp(ref min, ref value, ref max) => {
+                add_types(add_types(min.calc_type()?, value.calc_type()?)?, max.calc_type()?)
+            }
+        }
+    }
+
+    /// Evaluate the expression in px, or as a plain number, resolving percentages against
+    /// `reference`. Relative lengths must already have been resolved.
+    pub fn evaluate(&self, reference: f32) -> f32 {
+        match *self {
+            Calc::Leaf(Value::Number(n)) => n,
+            Calc::Leaf(ref value) => value.to_px_of(reference),
+            Calc::Sum(ref a, ref b) => a.evaluate(reference) + b.evaluate(reference),
+            Calc::Difference(ref a, ref b) => a.evaluate(reference) - b.evaluate(reference),
+            Calc::Product(ref a, ref b) => a.evaluate(reference) * b.evaluate(reference),
+            Calc::Quotient(ref a, ref b) => a.evaluate(reference) / b.evaluate(reference),
+            Calc::Min(ref args) => {
+                args.iter().map(|arg| arg.evaluate(reference)).fold(f32::INFINITY, f32::min)
+            }
+            Calc::Max(ref args) => {
+                args.iter().map(|arg| arg.evaluate(reference))
+                    .fold(f32::NEG_INFINITY, f32::max)
+            }
+            Calc::Clamp(ref min, ref value, ref max) => {
+                value.evaluate(reference).min(max.evaluate(reference)).max(min.evaluate(reference))
+            }
+        }
+    }
+
+    /// Resolve the relative lengths in the expression. Expressions without percentages are
+    /// evaluated to a single length or number.
+    pub fn resolve(&self, ctx: &LengthContext) -> Value {
+        let resolved = self.map_leaves(&|value| value.resolve(ctx));
+        match resolved.calc_type() {
+            Some(CalcType::Number) => Value::Number(resolved.evaluate(0.0)),
+            Some(CalcType::Length) => Value::Length(resolved.evaluate(0.0), Unit::Px),
+            _ => Value::Calc(Box::new(resolved))
+        }
+    }
+
+    fn map_leaves(&self, f: &dyn Fn(&Value) -> Value) -> Calc {
+        let map = |calc: &Calc| Box::new(calc.map_leaves(f));
+        match *self {
+            Calc::Leaf(ref value) => Calc::Leaf(f(value)),
+            Calc::Sum(ref a, ref b) => Calc::Sum(map(a), map(b)),
+            Calc::Difference(ref a, ref b) => Calc::Difference(map(a), map(b)),
+            Calc::Product(ref a, ref b) => Calc::Product(map(a), map(b)),
+            Calc::Quotient(ref a, ref b) => Calc::Quotient(map(a), map(b)),
+            Calc::Min(ref args) => Calc::Min(args.iter().map(|arg| arg.map_leaves(f)).collect()),
+            Calc::Max(ref args) => Calc::Max(args.iter().map(|arg| arg.map_leaves(f)).collect()),
+            Calc::Clamp(ref min, ref value, ref max) => Calc::Clamp(map(min), map(value), map(max))
+        }
+    }
+}
+
+// The type of the sum of two expressions. Lengths and percentages can be added together, but
+// not to numbers.
+fn add_types(a: CalcType, b: CalcType) -> Option<CalcType> {
+    match (a, b) {
+        _ if a == b => Some(a),
+        (CalcType::Number, _) | (_, CalcType::Number) => None,
+        _ => Some(CalcType::LengthPercentage)
+    }
+}
+
+// The default styles applied to every document.
+const USER_AGENT_CSS: &str = "
+    head, style, script, title, meta, link { display: none; }
+    table { display: table; border-spacing: 2px; }
+    caption { display: table-caption; }
+    thead { display: table-header-group; vertical-align: middle; }
+    tbody { display: table-row-group; vertical-align: middle; }
+    tfoot { display: table-footer-group; vertical-align: middle; }
+    tr { display: table-row; vertical-align: inherit; }
+    td, th { display: table-cell; vertical-align: inherit; padding: 1px; }
+    col { display: table-column; }
+    colgroup { display: table-column-group; }
+";
+
+/// The user agent stylesheet, lowest in the cascade.
+pub fn user_agent() -> Stylesheet {
+    parse(String::from(USER_AGENT_CSS), Origin::UserAgent)
+}
+
+/// Parse a whole CSS stylesheet.
+pub fn parse(source: String, origin: Origin) -> Stylesheet {
+    let mut parser = Parser::new(source);
+    let rules = parser.parse_rules(&[]);
+    Stylesheet {
+        rules,
+        origin,
+        imports: parser.imports,
+        font_faces: parser.font_faces,
+    }
+}
+
+/// Parse a single property value, such as `10px` or `#ff0000`, or `None` if it's invalid.
+pub fn parse_value(source: String) -> Option<Value> {
+    let mut parser = Parser::new(source);
+    parser.consume_whitespace();
+    parser.parse_value()
+}
+
+/// Parse the space-separated component values of a declaration, such as `1px solid #000000`,
+/// or `None` if any of them is invalid.
+pub fn parse_values(source: String) -> Option<Vec<Value>> {
+    let mut parser = Parser::new(source + ";");
+    parser.parse_values()
+}
+
+// Build the declarations for a value that has to stay unparsed until computed-value time.
+fn unparsed_declarations(name: String, text: String, important: bool) -> Vec<Declaration> {
+    let declaration = |name: &str, value: Value| Declaration {
+        name: String::from(name),
+        value,
+        important,
+    };
+
+    // The CSS-wide keywords still apply to custom properties.
+    match &*text.to_ascii_lowercase() {
+        "inherit" | "initial" | "unset" => {
+            return vec![declaration(&name, Value::Keyword(text.to_ascii_lowercase()))];
+        }
+        _ => {}
+    }
+
+    match properties::longhands(&name) {
+        Some(longhands) => longhands.iter()
+            .map(|longhand| {
+                declaration(longhand, Value::Unparsed(text.clone(), Some(name.clone())))
+            })
+            .collect(),
+        None => vec![declaration(&name, Value::Unparsed(text, None))]
+    }
+}
+
+// Relative lengths in media queries refer to the initial font size.
+const MEDIA_LENGTH_CONTEXT: LengthContext = LengthContext {
+    font_size: 16.0,
+    root_font_size: 16.0,
+    viewport_width: 0.0,
+    viewport_height: 0.0,
+};
+
+// Parse a resolution like `2dppx`, `2x`, `192dpi` or `75dpcm`, in dppx.
+fn parse_resolution(value: &str) -> Option<f32> {
+    let split = value.find(|c: char| c.is_alphabetic()).unwrap_or(value.len());
+    let number: f32 = match value[..split].parse() {
+        Ok(number) => number,
+        Err(_) => return None
+    };
+    match &value[split..] {
+        "dppx" | "x" => Some(number),
+        "dpi" => Some(number / 96.0),
+        "dpcm" => Some(number * 2.54 / 96.0),
+        _ => None
+    }
+}
+
+// The urls in an `@font-face` `src` list like `url(a.woff2) format("woff2"), local(Arial)`.
+fn url_sources(text: &str) -> Vec<String> {
+    let mut sources = Vec::new();
+    let mut depth = 0;
+    let mut start = 0;
+    for (i, c) in text.char_indices().chain(Some((text.len(), ','))) {
+        match c {
+            '(' => depth += 1,
+            ')' => depth -= 1,
+            ',' if depth == 0 => {
+                let source = text[start..i].trim();
+                if source.to_ascii_lowercase().starts_with("url(") {
+                    let end = source.find(')').unwrap_or(source.len());
+                    let url = source[4..end].trim().trim_matches(|c| c == '"' || c == '\'');
+                    sources.push(url.to_string());
+                }
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    sources
+}
+
+struct Parser {
+    pos: usize,
+    input: String,
+    imports: Vec<Import>,
+    font_faces: Vec<FontFaceRule>,
+}
+
+impl Parser {
+    fn new(input: String) -> Parser {
+        Parser {
+            pos: 0,
+            input,
+            imports: Vec::new(),
+            font_faces: Vec::new(),
+        }
+    }
+
+    /// Parse a list of rule sets and at-rules, separated by optional whitespace, up to the end of
+    /// the input or of the enclosing block.
+    ///
+    /// `media` holds the queries of the enclosing `@media` blocks.
+    fn parse_rules(&mut self, media: &[MediaQueryList]) -> Vec<Rule> {
+        let mut rules = Vec::new();
+        loop {
+            self.consume_whitespace();
+            if self.eof() || self.next_char() == '}' { break }
+            if self.next_char() == '@' {
+                rules.extend(self.parse_at_rule(media));
+            } else {
+                rules.extend(self.parse_rule(media));
+            }
+        }
+        rules
+    }
+
+    /// Parse a rule set: `<selectors> { <declarations> }`.
+    ///
+    /// A rule set with a selector that can't be parsed, like an unsupported pseudo-class, is
+    /// skipped as a whole.
+    fn parse_rule(&mut self, media: &[MediaQueryList]) -> Option<Rule> {
+        let selectors = match self.parse_selectors() {
+            Some(selectors) => selectors,
+            None => {
+                self.skip_rule();
+                return None;
+            }
+        };
+        Some(Rule {
+            selectors,
+            declarations: self.parse_declarations(),
+            media: media.to_vec(),
+        })
+    }
+
+    /// Parse an at-rule, returning the rule sets it contains. Unsupported at-rules are skipped.
+    fn parse_at_rule(&mut self, media: &[MediaQueryList]) -> Vec<Rule> {
+        assert!(self.consume_char() == '@');
+        let name = self.parse_identifier().to_ascii_lowercase();
+        match &*name {
+            "media" => {
+                let mut media = media.to_vec();
+                media.push(self.parse_media_query_list());
+                assert!(self.consume_char() == '{');
+                let rules = self.parse_rules(&media);
+                assert!(self.consume_char() == '}');
+                rules
+            }
+            // `@import` is only valid at the top level.
+            "import" if media.is_empty() => {
+                self.parse_import();
+                Vec::new()
+            }
+            "font-face" => {
+                self.parse_font_face();
+                Vec::new()
+            }
+            _ => {
+                self.skip_at_rule();
+                Vec::new()
+            }
+        }
+    }
+
+    /// Parse the rest of `@import "<url>" <media>;` or `@import url(<url>) <media>;`. A
+    /// malformed or unfinished import is skipped.
+    fn parse_import(&mut self) {
+        self.consume_whitespace();
+        let url = if self.starts_with("\"") || self.starts_with("'") {
+            Some(self.parse_string())
+        } else {
+            self.parse_url()
+        };
+        if let Some(url) = url {
+            let media = self.parse_media_query_list();
+            if self.starts_with(";") {
+                self.consume_char();
+                self.imports.push(Import { url, media });
+                return;
+            }
+        }
+        self.skip_at_rule();
+    }
+
+    /// Parse `url(<url>)`, where the url may be quoted, or return `None` if it's malformed.
+    fn parse_url(&mut self) -> Option<String> {
+        if !self.parse_identifier().eq_ignore_ascii_case("url") || !self.starts_with("(") {
+            return None;
+        }
+        self.consume_char();
+        self.consume_whitespace();
+        let url = if self.starts_with("\"") || self.starts_with("'") {
+            self.parse_string()
+        } else {
+            self.consume_while(|c| c != ')' && !c.is_whitespace())
+        };
+        self.consume_whitespace();
+        if !self.starts_with(")") {
+            return None;
+        }
+        self.consume_char();
+        Some(url)
+    }
+
+    /// Parse the descriptors of an `@font-face` block.
+    fn parse_font_face(&mut self) {
+        self.consume_whitespace();
+        assert!(self.consume_char() == '{');
+        let mut font_face = FontFaceRule {
+            family: String::new(),
+            sources: Vec::new(),
+            weight: 400,
+            italic: false,
+        };
+        loop {
+            self.consume_whitespace();
+            if self.next_char() == '}' {
+                self.consume_char();
+                break;
+            }
+            let name = self.parse_identifier().to_ascii_lowercase();
+            self.consume_whitespace();
+            assert!(self.consume_char() == ':');
+            self.consume_whitespace();
+            match &*name {
+                "font-family" => {
+                    font_face.family = self.consume_raw_value()
+                                           .trim_matches(|c| c == '"' || c == '\'').to_string();
+                }
+                "font-weight" => {
+                    font_face.weight = match &*self.consume_raw_value().to_ascii_lowercase() {
+                        "bold" => 700,
+                        "normal" => 400,
+                        weight => weight.parse().unwrap_or(400)
+                    };
+                }
+                "font-style" => {
+                    font_face.italic = self.consume_raw_value() != "normal";
+                }
+                "src" => {
+                    font_face.sources = url_sources(&self.consume_raw_value());
+                }
+                _ => { self.consume_raw_value(); }
+            }
+            if self.next_char() == ';' {
+                self.consume_char();
+            }
+        }
+        if !font_face.family.is_empty() {
+            self.font_faces.push(font_face);
+        }
+    }
+
+    /// Skip the rest of an at-rule, up to its `;` or the end of its block.
+    fn skip_at_rule(&mut self) {
+        let mut depth = 0;
+        while !self.eof() {
+            match self.consume_char() {
+                ';' if depth == 0 => break,
+                '{' => depth += 1,
+                '}' => {
+                    depth -= 1;
+                    if depth == 0 { break }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Skip the rest of a rule set, up to the end of its block.
+    fn skip_rule(&mut self) {
+        while !self.eof() && self.next_char() != '{' {
+            self.consume_char();
+        }
+        self.skip_at_rule();
+    }
+
+    // Methods for parsing media queries:
+
+    /// Parse a comma-separated list of media queries, up to the `{` of a block or a `;`.
+    fn parse_media_query_list(&mut self) -> MediaQueryList {
+        let mut queries = Vec::new();
+        loop {
+            self.consume_whitespace();
+            if self.eof() || self.next_char() == '{' || self.next_char() == ';' { break }
+            queries.push(self.parse_media_query());
+            self.consume_whitespace();
+            if !self.eof() && self.next_char() == ',' {
+                self.consume_char();
+            }
+        }
+        MediaQueryList { queries }
+    }
+
+    /// Parse one query, e.g.: `only screen and (max-width: 600px)`
+    fn parse_media_query(&mut self) -> MediaQuery {
+        let mut query = MediaQuery {
+            negated: false,
+            media_type: MediaType::All,
+            features: Vec::new(),
+        };
+
+        if self.next_char() != '(' {
+            let mut name = self.parse_identifier().to_ascii_lowercase();
+            if name == "not" || name == "only" {
+                query.negated = name == "not";
+                self.consume_whitespace();
+                name = self.parse_identifier().to_ascii_lowercase();
+            }
+            query.media_type = match &*name {
+                "all" => MediaType::All,
+                "screen" => MediaType::Screen,
+                "print" => MediaType::Print,
+                _ => MediaType::Unknown
+            };
+            self.consume_whitespace();
+            if !self.starts_with("and") {
+                return query;
+            }
+            self.parse_identifier();
+            self.consume_whitespace();
+        }
+
+        loop {
+            query.features.push(self.parse_media_feature());
+            self.consume_whitespace();
+            if !self.starts_with("and") { break }
+            self.parse_identifier();
+            self.consume_whitespace();
+        }
+        query
+    }
+
+    /// Parse a parenthesized feature, e.g.: `(min-width: 40em)`, `(width < 600px)` or
+    /// `(orientation)`.
+    fn parse_media_feature(&mut self) -> MediaFeature {
+        assert!(self.consume_char() == '(');
+        self.consume_whitespace();
+        let mut name = self.parse_identifier().to_ascii_lowercase();
+        self.consume_whitespace();
+
+        let mut comparison = self.consume_while(|c| c == ':' || c == '<' || c == '>' || c == '=');
+        self.consume_whitespace();
+        let value = if comparison.is_empty() {
+            None
+        } else {
+            Some(self.consume_while(|c| c != ')').trim().to_ascii_lowercase())
+        };
+        assert!(self.consume_char() == ')');
+
+        // The `min-` and `max-` prefixes mean the same as the `>=` and `<=` comparisons.
+        if name.starts_with("min-") || name.starts_with("max-") {
+            comparison = String::from(if name.starts_with("min-") { ">=" } else { "<=" });
+            name = name[4..].to_string();
+        }
+        let range = |value: f32| match &*comparison {
+            ">=" => Range::Min(value),
+            "<=" => Range::Max(value),
+            ">" => Range::Greater(value),
+            "<" => Range::Less(value),
+            _ => Range::Exact(value)
+        };
+
+        match (&*name, value) {
+            ("width", None) => MediaFeature::Width(Range::NonZero),
+            ("height", None) => MediaFeature::Height(Range::NonZero),
+            ("resolution", None) => MediaFeature::Resolution(Range::NonZero),
+            ("width", Some(v)) => match parse_value(v) {
+                Some(v) => MediaFeature::Width(range(v.resolve(&MEDIA_LENGTH_CONTEXT).to_px())),
+                None => MediaFeature::Unknown
+            },
+            ("height", Some(v)) => match parse_value(v) {
+                Some(v) => MediaFeature::Height(range(v.resolve(&MEDIA_LENGTH_CONTEXT).to_px())),
+                None => MediaFeature::Unknown
+            },
+            ("resolution", Some(v)) => match parse_resolution(&v) {
+                Some(dppx) => MediaFeature::Resolution(range(dppx)),
+                None => MediaFeature::Unknown
+            },
+            ("orientation", Some(v)) => match &*v {
+                "portrait" => MediaFeature::Orientation(Orientation::Portrait),
+                "landscape" => MediaFeature::Orientation(Orientation::Landscape),
+                _ => MediaFeature::Unknown
+            },
+            ("prefers-color-scheme", Some(v)) => match &*v {
+                "light" => MediaFeature::PrefersColorScheme(ColorScheme::Light),
+                "dark" => MediaFeature::PrefersColorScheme(ColorScheme::Dark),
+                _ => MediaFeature::Unknown
+            },
+            _ => MediaFeature::Unknown
+        }
+    }
+
+    /// Parse a comma-separated list of selectors, or return `None` if any of them is invalid.
+    fn parse_selectors(&mut self) -> Option<Vec<Selector>> {
+        let mut selectors = Vec::new();
+        loop {
+            selectors.push(self.parse_selector()?);
+            match self.next_char() {
+                ',' => { self.consume_char(); self.consume_whitespace(); }
+                '{' => break,
+                _   => return None
+            }
+        }
+        // Return selectors with highest specificity first, for use in matching.
+        selectors.sort_by_key(|selector| Reverse(selector.specificity()));
+        Some(selectors)
+    }
+
+    /// Parse a chain of simple selectors joined by combinators, e.g.: `ul > li.item a`
+    fn parse_selector(&mut self) -> Option<Selector> {
+        let mut selector = Selector::Simple(self.parse_simple_selector()?);
+        loop {
+            let whitespace = self.consume_while(char::is_whitespace);
+            if self.eof() {
+                return None;
+            }
+            let combinator = match self.next_char() {
+                ',' | '{' | ')' => break,
+                '>' => Combinator::Child,
+                '+' => Combinator::NextSibling,
+                '~' => Combinator::SubsequentSibling,
+                _ if !whitespace.is_empty() => Combinator::Descendant,
+                _ => return None
+            };
+            if combinator != Combinator::Descendant {
+                self.consume_char();
+                self.consume_whitespace();
+            }
+            // A combinator must have a selector on its right, even if it's just `*`.
+            let start = self.pos;
+            let right = self.parse_simple_selector()?;
+            if self.pos == start {
+                return None;
+            }
+            selector = Selector::Complex(Box::new(selector), combinator, right);
+        }
+        Some(selector)
+    }
+
+    /// Parse one simple selector, e.g.: `type#id.class1.class2.class3`
+    fn parse_simple_selector(&mut self) -> Option<SimpleSelector> {
+        let mut selector = SimpleSelector {
+            tag_name: None,
+            id: None,
+            class: Vec::new(),
+            attributes: Vec::new(),
+            pseudo_classes: Vec::new(),
+            pseudo_element: None,
+        };
+        while !self.eof() {
+            match self.next_char() {
+                '*' => {
+                    // universal selector
+                    self.consume_char();
+                }
+                '[' => {
+                    selector.attributes.push(self.parse_attr_selector()?);
+                }
+                ':' => {
+                    self.consume_char();
+                    // The pseudo-elements from CSS 2 can also be written with a single colon.
+                    if self.starts_with(":") || self.starts_with("before")
+                            || self.starts_with("after") {
+                        if self.starts_with(":") {
+                            self.consume_char();
+                        }
+                        selector.pseudo_element = Some(self.parse_pseudo_element()?);
+                    } else {
+                        selector.pseudo_classes.push(self.parse_pseudo_class()?);
+                    }
+                }
+                '#' => {
+                    self.consume_char();
+                    selector.id = Some(self.parse_identifier());
+                }
+                '.' => {
+                    self.consume_char();
+                    selector.class.push(self.parse_identifier());
+                }
+                c if valid_identifier_char(c) => {
+                    selector.tag_name = Some(self.parse_identifier());
+                }
+                _ => break
+            }
+        }
+        Some(selector)
+    }
+
+    /// Parse the name and any argument of a pseudo-class, after its `:`, or return `None` if
+    /// it's unsupported or malformed.
+    fn parse_pseudo_class(&mut self) -> Option<PseudoClass> {
+        let name = self.parse_identifier().to_ascii_lowercase();
+        if !self.starts_with("(") {
+            return Some(match &*name {
+                "root" => PseudoClass::Root,
+                "empty" => PseudoClass::Empty,
+                "first-child" => PseudoClass::FirstChild,
+                "last-child" => PseudoClass::LastChild,
+                "only-child" => PseudoClass::OnlyChild,
+                "first-of-type" => PseudoClass::FirstOfType,
+                "last-of-type" => PseudoClass::LastOfType,
+                "only-of-type" => PseudoClass::OnlyOfType,
+                _ => return None
+            });
+        }
+
+        self.consume_char();
+        let pseudo = match &*name {
+            "nth-child" => PseudoClass::NthChild(self.parse_nth()?),
+            "nth-last-child" => PseudoClass::NthLastChild(self.parse_nth()?),
+            "nth-of-type" => PseudoClass::NthOfType(self.parse_nth()?),
+            "nth-last-of-type" => PseudoClass::NthLastOfType(self.parse_nth()?),
+            "not" => PseudoClass::Not(self.parse_selector_arguments()?),
+            "is" => PseudoClass::Is(self.parse_selector_arguments()?),
+            "where" => PseudoClass::Where(self.parse_selector_arguments()?),
+            _ => return None
+        };
+        if self.eof() || self.consume_char() != ')' {
+            return None;
+        }
+        Some(pseudo)
+    }
+
+    /// Parse the name of a pseudo-element, after its `::`, or return `None` if it's
+    /// unsupported.
+    fn parse_pseudo_element(&mut self) -> Option<PseudoElement> {
+        let name = self.parse_identifier().to_ascii_lowercase();
+        match &*name {
+            "before" => Some(PseudoElement::Before),
+            "after" => Some(PseudoElement::After),
+            _ => None
+        }
+    }
+
+    /// Parse the comma-separated selector list argument of `:not()`, `:is()` or `:where()`.
+    fn parse_selector_arguments(&mut self) -> Option<Vec<Selector>> {
+        let mut selectors = Vec::new();
+        loop {
+            self.consume_whitespace();
+            selectors.push(self.parse_selector()?);
+            if self.next_char() != ',' {
+                break;
+            }
+            self.consume_char();
+        }
+        Some(selectors)
+    }
+
+    /// Parse an `An+B` expression, or one of the keywords `odd` and `even`. Returns `None` if
+    /// the expression is malformed.
+    ///
+    /// https://www.w3.org/TR/css-syntax-3/#anb-microsyntax
+    fn parse_nth(&mut self) -> Option<Nth> {
+        let expr = self.consume_while(|c| c != ')').trim().to_ascii_lowercase();
+        match &*expr {
+            "odd" => return Some(Nth { a: 2, b: 1 }),
+            "even" => return Some(Nth { a: 2, b: 0 }),
+            _ => {}
+        }
+
+        let (a, b) = match expr.find('n') {
+            Some(n) => (&expr[..n], &expr[n + 1..]),
+            None => return Some(Nth { a: 0, b: expr.parse().ok()? })
+        };
+        let a = match a {
+            "" | "+" => 1,
+            "-" => -1,
+            a => a.parse().ok()?
+        };
+        // The sign of B is required, and may have whitespace on either side: `2n + 1`.
+        let b: String = b.chars().filter(|c| !c.is_whitespace()).collect();
+        let b = match b.chars().next() {
+            None => 0,
+            Some('+') | Some('-') => b.parse().ok()?,
+            Some(_) => return None
+        };
+        Some(Nth { a, b })
+    }
+
+    /// Parse an attribute selector enclosed in `[ ... ]`, or return `None` if it's malformed.
+    fn parse_attr_selector(&mut self) -> Option<AttrSelector> {
+        assert!(self.consume_char() == '[');
+        self.consume_whitespace();
+        let name = self.parse_identifier();
+        self.consume_whitespace();
+        if name.is_empty() || self.eof() {
+            return None;
+        }
+
+        let operator = match self.next_char() {
+            ']' => {
+                self.consume_char();
+                return Some(AttrSelector {
+                    name,
+                    operator: AttrOperator::Exists,
+                    case_insensitive: false,
+                });
+            }
+            '=' => AttrOperator::Equals,
+            '~' => AttrOperator::Includes,
+            '|' => AttrOperator::DashMatch,
+            '^' => AttrOperator::Prefix,
+            '$' => AttrOperator::Suffix,
+            '*' => AttrOperator::Substring,
+            _ => return None
+        };
+        if self.consume_char() != '=' {
+            if self.eof() || self.next_char() != '=' {
+                return None;
+            }
+            self.consume_char();
+        }
+        self.consume_whitespace();
+        if self.eof() {
+            return None;
+        }
+        let value = match self.next_char() {
+            '"' | '\'' => self.parse_string(),
+            _ => self.parse_identifier()
+        };
+        self.consume_whitespace();
+
+        // An optional `i` or `s` flag selects case-insensitive or case-sensitive matching.
+        let flag = self.parse_identifier();
+        self.consume_whitespace();
+        if self.eof() || self.next_char() != ']' {
+            return None;
+        }
+        self.consume_char();
+
+        Some(AttrSelector {
+            name,
+            operator: operator(value),
+            case_insensitive: flag.eq_ignore_ascii_case("i"),
+        })
+    }
+
+    /// Parse a quoted string. An unclosed string runs to the end of the input.
+    fn parse_string(&mut self) -> String {
+        let open_quote = self.consume_char();
+        assert!(open_quote == '"' || open_quote == '\'');
+        let value = self.consume_while(|c| c != open_quote);
+        if !self.eof() {
+            self.consume_char();
+        }
+        value
+    }
+
+    /// Parse a list of declarations enclosed in `{ ... }`.
+    fn parse_declarations(&mut self) -> Vec<Declaration> {
+        assert!(self.consume_char() == '{');
+        let mut declarations = Vec::new();
+        loop {
+            self.consume_whitespace();
+            if self.eof() {
+                break;
+            }
+            if self.next_char() == '}' {
+                self.consume_char();
+                break;
+            }
+            declarations.extend(self.parse_declaration());
+        }
+        declarations
+    }
+
+    /// Parse one `<property>: <value>;` declaration. The `;` may be left out before the `}` that
+    /// ends the block.
+    ///
+    /// Shorthand properties are expanded into the longhand declarations they stand for. A
+    /// malformed declaration is skipped, up to the next `;` or the end of the block.
+    fn parse_declaration(&mut self) -> Vec<Declaration> {
+        let property_name = self.parse_identifier();
+        self.consume_whitespace();
+        if property_name.is_empty() || self.eof() || self.next_char() != ':' {
+            self.skip_declaration();
+            return Vec::new();
+        }
+        self.consume_char();
+
+        // Custom properties and values with `var()` can't be parsed before substitution.
+        let start = self.pos;
+        let text = self.consume_raw_value();
+        let end = self.pos;
+        let custom = property_name.starts_with("--");
+        if custom || text.to_ascii_lowercase().contains("var(") {
+            let important = self.parse_important();
+            if !self.end_declaration() {
+                return Vec::new();
+            }
+            return match important {
+                Some(important) => unparsed_declarations(property_name, text, important),
+                None => Vec::new()
+            };
+        }
+
+        self.pos = start;
+        let values = self.parse_values();
+        self.pos = end;
+        let important = self.parse_important();
+        if !self.end_declaration() {
+            return Vec::new();
+        }
+
+        // A declaration with a value or annotation that can't be parsed is ignored.
+        match (values, important) {
+            (Some(values), Some(important)) => {
+                properties::expand(&property_name, values, important)
+            }
+            _ => Vec::new()
+        }
+    }
+
+    /// Consume the `;` at the end of a declaration, unless the block ends first. Returns false,
+    /// skipping the rest of the declaration, if anything else comes first.
+    fn end_declaration(&mut self) -> bool {
+        self.consume_whitespace();
+        if self.eof() || self.next_char() == '}' {
+            return true;
+        }
+        let ended = self.next_char() == ';';
+        self.skip_declaration();
+        ended
+    }
+
+    /// Skip the rest of a declaration, up to and including its `;`, or up to the `}` that ends
+    /// the block.
+    fn skip_declaration(&mut self) {
+        loop {
+            self.consume_raw_value();
+            if self.eof() || self.next_char() == '}' {
+                break;
+            }
+            if self.consume_char() == ';' {
+                break;
+            }
+        }
+    }
+
+    /// Consume the text of a value up to its end or `!important`, skipping over nested blocks
+    /// and strings. Returns the text with surrounding whitespace trimmed.
+    fn consume_raw_value(&mut self) -> String {
+        let mut text = String::new();
+        let mut depth = 0;
+        while !self.eof() {
+            match self.next_char() {
+                ';' | '!' | '}' if depth == 0 => break,
+                '(' | '[' | '{' => depth += 1,
+                ')' | ']' | '}' => depth -= 1,
+                '"' | '\'' => {
+                    let quote = self.next_char();
+                    text.push(quote);
+                    text.push_str(&self.parse_string());
+                    text.push(quote);
+                    continue;
+                }
+                _ => {}
+            }
+            text.push(self.consume_char());
+        }
+        text.trim().to_string()
+    }
+
+    /// Parse the component values of a declaration, up to its end or `!important`.
+    ///
+    /// The `,` and `/` separators are kept as keywords so shorthands can tell their parts apart.
+    /// Returns `None` at the first invalid value, leaving the rest of the declaration unparsed.
+    fn parse_values(&mut self) -> Option<Vec<Value>> {
+        let mut values = Vec::new();
+        loop {
+            self.consume_whitespace();
+            match self.next_char() {
+                ';' | '!' | '}' => break,
+                ',' | '/' => values.push(Value::Keyword(self.consume_char().to_string())),
+                '"' | '\'' => values.push(Value::Str(self.parse_string())),
+                _ => {
+                    let start = self.pos;
+                    values.push(self.parse_value()?);
+                    if self.pos == start {
+                        return None;
+                    }
+                }
+            }
+        }
+        Some(values)
+    }
+
+    /// Parse an optional `!important` annotation, or return `None` if there's some other `!`.
+    fn parse_important(&mut self) -> Option<bool> {
+        if !self.starts_with("!") {
+            return Some(false);
+        }
+        self.consume_char();
+        self.consume_whitespace();
+        if !self.parse_identifier().eq_ignore_ascii_case("important") {
+            return None;
+        }
+        self.consume_whitespace();
+        Some(true)
+    }
+
+    // Methods for parsing values:
+
+    /// Parse one component value, or return `None` if it's invalid.
+    fn parse_value(&mut self) -> Option<Value> {
+        match self.next_char() {
+            '0'..='9' | '.' => self.parse_length(),
+            '-' | '+' if self.starts_with_number() => self.parse_length(),
+            '#' => self.parse_color(),
+            '[' => Some(Value::Tracks(vec![Track::Names(self.parse_line_names()?)])),
+            _ => {
+                let name = self.parse_identifier();
+                if !self.eof() && self.next_char() == '(' {
+                    self.parse_function(name)
+                } else {
+                    Some(Value::Keyword(name))
+                }
+            }
+        }
+    }
+
+    /// Parse the arguments and closing parenthesis of a function value.
+    fn parse_function(&mut self, name: String) -> Option<Value> {
+        Some(match &*name.to_ascii_lowercase() {
+            "calc" | "min" | "max" | "clamp" => {
+                Value::Calc(Box::new(self.parse_math_function(&name)?))
+            }
+            "attr" | "counter" | "counters" => {
+                Value::Content(vec![self.parse_content_function(&name.to_ascii_lowercase())?])
+            }
+            "minmax" | "repeat" => {
+                Value::Tracks(vec![self.parse_track_function(&name.to_ascii_lowercase())?])
+            }
+            function if is_transform_function(function) => {
+                Value::Transform(vec![name.clone() + &self.consume_arguments()])
+            }
+            _ => return None
+        })
+    }
+
+    /// Parse the arguments of `attr()`, `counter()` or `counters()`, after the function name.
+    /// Returns `None` if they're malformed or there are too few or too many of them.
+    fn parse_content_function(&mut self, name: &str) -> Option<ContentItem> {
+        assert!(self.consume_char() == '(');
+        let mut args = Vec::new();
+        loop {
+            self.consume_whitespace();
+            if self.eof() {
+                return None;
+            }
+            // Only a string, like the separator of `counters()`, can be empty.
+            args.push(match self.next_char() {
+                '"' | '\'' => self.parse_string(),
+                _ => match self.parse_identifier() {
+                    ref identifier if identifier.is_empty() => return None,
+                    identifier => identifier
+                }
+            });
+            self.consume_whitespace();
+            if self.eof() {
+                return None;
+            }
+            match self.consume_char() {
+                ',' => {}
+                ')' => break,
+                _ => return None
+            }
+        }
+
+        // Counters are shown as decimal numbers unless a style is given.
+        let style = |i: usize| args.get(i).cloned().unwrap_or(String::from("decimal"));
+        Some(match (name, args.len()) {
+            ("attr", 1) => ContentItem::Attr(args[0].clone()),
+            ("counter", 1) | ("counter", 2) => ContentItem::Counter(args[0].clone(), style(1)),
+            ("counters", 2) | ("counters", 3) => {
+                ContentItem::Counters(args[0].clone(), args[1].clone(), style(2))
+            }
+            _ => return None
+        })
+    }
+
+    /// Parse a bracketed list of grid line names, like `[main-start sidebar]`.
+    /// Returns `None` if anything but names is in the brackets.
+    fn parse_line_names(&mut self) -> Option<Vec<String>> {
+        assert!(self.consume_char() == '[');
+        let mut names = Vec::new();
+        loop {
+            self.consume_whitespace();
+            if self.eof() {
+                return None;
+            }
+            if self.next_char() == ']' {
+                self.consume_char();
+                return Some(names);
+            }
+            let name = self.parse_identifier();
+            if name.is_empty() {
+                return None;
+            }
+            names.push(name);
+        }
+    }
+
+    /// Parse the arguments of `minmax()` or `repeat()`, after the function name. Returns `None`
+    /// if they're malformed, like a `repeat()` count that isn't a positive integer.
+    fn parse_track_function(&mut self, name: &str) -> Option<Track> {
+        assert!(self.consume_char() == '(');
+        let first = self.parse_track_argument()?;
+        if !self.starts_with(",") {
+            return None;
+        }
+        self.consume_char();
+        if name == "minmax" {
+            let max = self.parse_track_argument()?;
+            if !self.starts_with(")") {
+                return None;
+            }
+            self.consume_char();
+            return Some(Track::Size(first, max));
+        }
+
+        let count = match first {
+            Value::Number(n) if n >= 1.0 && n.fract() == 0.0 => Repetitions::Count(n as u32),
+            Value::Keyword(ref k) if k == "auto-fill" => Repetitions::AutoFill,
+            Value::Keyword(ref k) if k == "auto-fit" => Repetitions::AutoFit,
+            _ => return None
+        };
+        let mut tracks = Vec::new();
+        loop {
+            self.consume_whitespace();
+            if self.starts_with(")") {
+                self.consume_char();
+                break;
+            }
+            match self.parse_track_argument()? {
+                Value::Tracks(parts) => tracks.extend(parts),
+                size => tracks.push(Track::Size(size.clone(), size))
+            }
+        }
+        Some(Track::Repeat(count, tracks))
+    }
+
+    /// Parse one value among the arguments of a track function, and any whitespace around it.
+    fn parse_track_argument(&mut self) -> Option<Value> {
+        self.consume_whitespace();
+        if self.eof() {
+            return None;
+        }
+        let start = self.pos;
+        let value = self.parse_value()?;
+        if self.pos == start {
+            return None;
+        }
+        self.consume_whitespace();
+        Some(value)
+    }
+
+    /// Consume the arguments of a function as written, with their parentheses.
+    fn consume_arguments(&mut self) -> String {
+        assert!(self.next_char() == '(');
+        let mut text = String::new();
+        let mut depth = 0;
+        loop {
+            let c = self.consume_char();
+            text.push(c);
+            match c {
+                '(' => depth += 1,
+                ')' if depth == 1 => return text,
+                ')' => depth -= 1,
+                _ => {}
+            }
+        }
+    }
+
+    // Methods for parsing math expressions:
+
+    /// Parse the arguments of `calc()`, `min()`, `max()` or `clamp()`, after the function name.
+    /// Returns `None` if the expression is malformed.
+    fn parse_math_function(&mut self, name: &str) -> Option<Calc> {
+        assert!(self.consume_char() == '(');
+        let mut args = Vec::new();
+        loop {
+            self.consume_whitespace();
+            args.push(self.parse_calc_sum()?);
+            self.consume_whitespace();
+            if self.eof() {
+                return None;
+            }
+            match self.consume_char() {
+                ',' => {}
+                ')' => break,
+                _ => return None
+            }
+        }
+
+        Some(match &*name.to_ascii_lowercase() {
+            "calc" if args.len() == 1 => args.remove(0),
+            "min" => Calc::Min(args),
+            "max" => Calc::Max(args),
+            "clamp" if args.len() == 3 => {
+                let max = args.pop().unwrap();
+                let value = args.pop().unwrap();
+                let min = args.pop().unwrap();
+                Calc::Clamp(Box::new(min), Box::new(value), Box::new(max))
+            }
+            _ => return None
+        })
+    }
+
+    /// Parse `<product> [ + <product> | - <product> ]*`. The operators need whitespace around
+    /// them, so they can't be confused with signs.
+    fn parse_calc_sum(&mut self) -> Option<Calc> {
+        let mut sum = self.parse_calc_product()?;
+        loop {
+            let start = self.pos;
+            self.consume_whitespace();
+            let operator = if self.eof() { ' ' } else { self.next_char() };
+            let spaced = self.pos > start &&
+                self.input[self.pos..].chars().nth(1).is_some_and(char::is_whitespace);
+            if !spaced || (operator != '+' && operator != '-') {
+                self.pos = start;
+                break;
+            }
+            self.consume_char();
+            self.consume_whitespace();
+            let product = Box::new(self.parse_calc_product()?);
+            sum = if operator == '+' {
+                Calc::Sum(Box::new(sum), product)
+            } else {
+                Calc::Difference(Box::new(sum), product)
+            };
+        }
+        Some(sum)
+    }
+
+    /// Parse `<value> [ * <value> | / <value> ]*`.
+    fn parse_calc_product(&mut self) -> Option<Calc> {
+        let mut product = self.parse_calc_value()?;
+        loop {
+            let start = self.pos;
+            self.consume_whitespace();
+            if self.eof() || (self.next_char() != '*' && self.next_char() != '/') {
+                self.pos = start;
+                break;
+            }
+            let operator = self.consume_char();
+            self.consume_whitespace();
+            let value = Box::new(self.parse_calc_value()?);
+            product = if operator == '*' {
+                Calc::Product(Box::new(product), value)
+            } else {
+                Calc::Quotient(Box::new(product), value)
+            };
+        }
+        Some(product)
+    }
+
+    /// Parse a number, length, percentage, parenthesized sum or nested math function.
+    fn parse_calc_value(&mut self) -> Option<Calc> {
+        if self.next_char() == '(' {
+            self.consume_char();
+            self.consume_whitespace();
+            let sum = self.parse_calc_sum()?;
+            self.consume_whitespace();
+            if self.eof() || self.consume_char() != ')' {
+                return None;
+            }
+            return Some(sum);
+        }
+        Some(match self.parse_value()? {
+            Value::Calc(calc) => *calc,
+            value @ Value::Number(_) | value @ Value::Length(..) => Calc::Leaf(value),
+            _ => return None
+        })
+    }
+
+    // Does the input continue with a signed number, like `-5px` or `+.5`?
+    fn starts_with_number(&self) -> bool {
+        matches!(self.input[self.pos..].chars().nth(1), Some(c) if c.is_ascii_digit() || c == '.')
+    }
+
+    // Does the input continue with the exponent of a number, like `e3` or `E-2`, rather than a
+    // unit like `em`?
+    fn starts_with_exponent(&self) -> bool {
+        let mut chars = self.input[self.pos..].chars();
+        match chars.next() {
+            Some('e') | Some('E') => {}
+            _ => return false
+        }
+        let digit = match chars.next() {
+            Some('-') | Some('+') => chars.next(),
+            c => c
+        };
+        matches!(digit, Some(c) if c.is_ascii_digit())
+    }
+
+    /// Parse a number, followed by a unit if it is a length. Returns `None` for an unknown unit.
+    fn parse_length(&mut self) -> Option<Value> {
+        let f = self.parse_float()?;
+        if !self.eof() && (self.next_char() == '%' || valid_identifier_char(self.next_char())) {
+            Some(Value::Length(f, self.parse_unit()?))
+        } else {
+            Some(Value::Number(f))
+        }
+    }
+
+    fn parse_float(&mut self) -> Option<f32> {
+        let digits = |c: char| c.is_ascii_digit() || c == '.';
+        let mut s = String::new();
+        if self.next_char() == '-' || self.next_char() == '+' {
+            s.push(self.consume_char());
+        }
+        s.push_str(&self.consume_while(digits));
+        if self.starts_with_exponent() {
+            s.push(self.consume_char());
+            if self.next_char() == '-' || self.next_char() == '+' {
+                s.push(self.consume_char());
+            }
+            s.push_str(&self.consume_while(digits));
+        }
+        s.parse().ok()
+    }
+
+    fn parse_unit(&mut self) -> Option<Unit> {
+        if self.starts_with("%") {
+            self.consume_char();
+            return Some(Unit::Percent);
+        }
+        Some(match &*self.parse_identifier().to_ascii_lowercase() {
+            "px" => Unit::Px,
+            "pt" => Unit::Pt,
+            "pc" => Unit::Pc,
+            "cm" => Unit::Cm,
+            "mm" => Unit::Mm,
+            "in" => Unit::In,
+            "em" => Unit::Em,
+            "rem" => Unit::Rem,
+            "vw" => Unit::Vw,
+            "vh" => Unit::Vh,
+            "vmin" => Unit::Vmin,
+            "vmax" => Unit::Vmax,
+            "fr" => Unit::Fr,
+            _ => return None
+        })
+    }
+
+    /// Parse a `#rgb`, `#rgba`, `#rrggbb` or `#rrggbbaa` color.
+    fn parse_color(&mut self) -> Option<Value> {
+        assert!(self.consume_char() == '#');
+        let hex = self.parse_identifier();
+        // A single digit stands for a pair of the same digit: `#f80` is `#ff8800`.
+        let digit = |i: usize| u8::from_str_radix(hex.get(i..i + 1)?, 16).ok().map(|d| d * 17);
+        let pair = |i: usize| u8::from_str_radix(hex.get(i * 2..i * 2 + 2)?, 16).ok();
+        let (r, g, b, a) = match hex.len() {
+            3 => (digit(0)?, digit(1)?, digit(2)?, 255),
+            4 => (digit(0)?, digit(1)?, digit(2)?, digit(3)?),
+            6 => (pair(0)?, pair(1)?, pair(2)?, 255),
+            8 => (pair(0)?, pair(1)?, pair(2)?, pair(3)?),
+            _ => return None
+        };
+        Some(Value::ColorValue(Color { r, g, b, a }))
+    }
+
+    /// Parse a property name or keyword.
+    fn parse_identifier(&mut self) -> String {
+        self.consume_while(valid_identifier_char)
+    }
+
+    /// Consume and discard zero or more whitespace characters and comments.
+    fn consume_whitespace(&mut self) {
+        loop {
+            self.consume_while(char::is_whitespace);
+            if !self.starts_with("/*") {
+                break;
+            }
+            // An unclosed comment runs to the end of the input.
+            self.pos = match self.input[self.pos + 2..].find("*/") {
+                Some(end) => self.pos + 2 + end + 2,
+                None => self.input.len()
+            };
+        }
+    }
+
+    /// Consume characters until `test` returns false.
+    fn consume_while<F>(&mut self, test: F) -> String
+            where F: Fn(char) -> bool {
+        let mut result = String::new();
+        while !self.eof() && test(self.next_char()) {
+            result.push(self.consume_char());
+        }
+        result
+    }
+
+    /// Return the current character, and advance self.pos to the next character.
+    fn consume_char(&mut self) -> char {
+        let mut iter = self.input[self.pos..].char_indices();
+        let (_, cur_char) = iter.next().unwrap();
+        let (next_pos, _) = iter.next().unwrap_or((1, ' '));
+        self.pos += next_pos;
+        cur_char
+    }
+
+    // Read the current character without consuming it.
+    fn next_char(&self) -> char {
+        self.input[self.pos..].chars().next().unwrap()
+    }
+
+    // Do the next characters start with the given string?
+    fn starts_with(&self, s: &str) -> bool {
+        self.input[self.pos ..].starts_with(s)
+    }
+
+    // Return true if all input is consumed.
+    fn eof(&self) -> bool {
+        self.pos >= self.input.len()
+    }
+}
+
+// Whether a lowercase function name is one of the `transform` functions.
+fn is_transform_function(name: &str) -> bool {
+    ["matrix", "perspective", "rotate", "scale", "skew", "translate"].iter()
+        .any(|prefix| name.starts_with(prefix))
+}
+
+fn valid_identifier_char(c: char) -> bool {
+    match c {
+        'a'..='z' | 'A'..='Z' | '0'..='9' | '-' | '_' => true, // TODO: Include U+00A0 and higher.
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn length(source: &str) -> Option<Value> {
+        parse_value(String::from(source))
+    }
+
+    #[test]
+    fn units() {
+        let units = [("px", Unit::Px), ("pt", Unit::Pt), ("pc", Unit::Pc), ("cm", Unit::Cm),
+                     ("mm", Unit::Mm), ("in", Unit::In), ("em", Unit::Em), ("rem", Unit::Rem),
+                     ("vw", Unit::Vw), ("vh", Unit::Vh), ("vmin", Unit::Vmin),
+                     ("vmax", Unit::Vmax), ("%", Unit::Percent), ("fr", Unit::Fr)];
+        for &(suffix, ref unit) in &units {
+            assert!(length(&format!("2.5{}", suffix)) == Some(Value::Length(2.5, unit.clone())));
+        }
+        assert!(length("1EM") == Some(Value::Length(1.0, Unit::Em)));
+        assert!(length("-3px") == Some(Value::Length(-3.0, Unit::Px)));
+    }
+
+    #[test]
+    fn absolute_lengths_to_px() {
+        assert_eq!(length("1in").unwrap().to_px(), 96.0);
+        assert_eq!(length("2.54cm").unwrap().to_px(), 96.0);
+        assert_eq!(length("25.4mm").unwrap().to_px(), 96.0);
+        assert_eq!(length("72pt").unwrap().to_px(), 96.0);
+        assert_eq!(length("6pc").unwrap().to_px(), 96.0);
+        assert_eq!(length("50%").unwrap().to_px_of(300.0), 150.0);
+    }
+
+    #[test]
+    fn relative_lengths_resolve() {
+        let ctx = LengthContext {
+            font_size: 10.0,
+            root_font_size: 16.0,
+            viewport_width: 800.0,
+            viewport_height: 600.0,
+        };
+        let px = |source| length(source).unwrap().resolve(&ctx).to_px();
+        assert_eq!(px("2em"), 20.0);
+        assert_eq!(px("2rem"), 32.0);
+        assert_eq!(px("10vw"), 80.0);
+        assert_eq!(px("10vh"), 60.0);
+        assert_eq!(px("10vmin"), 60.0);
+        assert_eq!(px("10vmax"), 80.0);
+        assert!(length("10%").unwrap().resolve(&ctx) == Value::Length(10.0, Unit::Percent));
+    }
+
+    #[test]
+    fn exponents() {
+        assert!(length("1e3") == Some(Value::Number(1000.0)));
+        assert!(length("1.5E-1px") == Some(Value::Length(0.15, Unit::Px)));
+        assert!(length("2e+1%") == Some(Value::Length(20.0, Unit::Percent)));
+        assert!(length("1em") == Some(Value::Length(1.0, Unit::Em)));
+    }
+
+    #[test]
+    fn hex_colors() {
+        let color = |r, g, b, a| Some(Value::ColorValue(Color { r, g, b, a }));
+        assert!(length("#f80") == color(255, 136, 0, 255));
+        assert!(length("#f808") == color(255, 136, 0, 136));
+        assert!(length("#ff8800") == color(255, 136, 0, 255));
+        assert!(length("#ff880080") == color(255, 136, 0, 128));
+        assert!(length("#ff888").is_none());
+        assert!(length("#ggg").is_none());
+    }
+
+    fn nth(argument: &str) -> Option<(i32, i32)> {
+        let mut parser = Parser::new(format!("{})", argument));
+        parser.parse_nth().map(|nth| (nth.a, nth.b))
+    }
+
+    #[test]
+    fn nth_expressions() {
+        assert_eq!(nth("odd"), Some((2, 1)));
+        assert_eq!(nth(" EVEN "), Some((2, 0)));
+        assert_eq!(nth("-n+3"), Some((-1, 3)));
+        assert_eq!(nth("+5"), Some((0, 5)));
+        assert_eq!(nth("3n"), Some((3, 0)));
+        assert_eq!(nth("n"), Some((1, 0)));
+        assert_eq!(nth("2n + 1"), Some((2, 1)));
+        assert_eq!(nth("2n- 1"), Some((2, -1)));
+    }
+
+    #[test]
+    fn malformed_nth_expressions() {
+        for argument in &["foo", "2n+", "2n 1", "3 n", "+ 5", "n-+1", ""] {
+            assert_eq!(nth(argument), None, "{}", argument);
+        }
+    }
+
+    #[test]
+    fn rules_with_unsupported_selectors_are_skipped() {
+        let sheet = parse(String::from("a:hover { color: red; } p::selection { color: red; } \
+                                        li:nth-child(foo) { color: red; } \
+                                        p:not(:focus) { color: red; } :lang(en) { } \
+                                        li:nth-child(2n + 1), p::before { width: 1px; }"),
+                          Origin::Author);
+        assert_eq!(sheet.rules.len(), 1);
+        assert_eq!(sheet.rules[0].selectors.len(), 2);
+        assert_eq!(sheet.rules[0].declarations[0].name, "width");
+    }
+
+    #[test]
+    fn dangling_combinators_are_rejected() {
+        let names = declaration_names("p > { color: red; } p + , a { color: red; } \
+                                       ul ~ { color: red; } p > * { width: 1px; }");
+        assert_eq!(names, [["width"]]);
+    }
+
+    #[test]
+    fn malformed_attribute_selectors_are_rejected() {
+        let names = declaration_names("[a!=b] { color: red; } [a~b] { color: red; } \
+                                       [a=b { color: red; } [=b] { color: red; } \
+                                       [a|=\"b\" i] { width: 1px; }");
+        assert_eq!(names, [["width"]]);
+    }
+
+    fn calc(source: &str) -> Calc {
+        match length(source) {
+            Some(Value::Calc(calc)) => *calc,
+            _ => panic!("{} isn't a math expression", source)
+        }
+    }
+
+    #[test]
+    fn math_expressions() {
+        assert_eq!(calc("calc(1px + 2px * 3)").evaluate(0.0), 7.0);
+        assert_eq!(calc("calc((1px + 2px) * 3)").evaluate(0.0), 9.0);
+        assert_eq!(calc("calc(10px / 4 - -1px)").evaluate(0.0), 3.5);
+        assert_eq!(calc("calc(50% - 10px)").evaluate(200.0), 90.0);
+        assert_eq!(calc("min(10px, 5%, 1in)").evaluate(100.0), 5.0);
+        assert_eq!(calc("max(1px, 2px, 3px)").evaluate(0.0), 3.0);
+        assert_eq!(calc("calc(2 * max(1px, 10%))").evaluate(100.0), 20.0);
+        let clamp = calc("clamp(10px, 50%, 100px)");
+        assert_eq!(clamp.evaluate(0.0), 10.0);
+        assert_eq!(clamp.evaluate(100.0), 50.0);
+        assert_eq!(clamp.evaluate(1000.0), 100.0);
+    }
+
+    #[test]
+    fn math_expression_types() {
+        let calc_type = |source| calc(source).calc_type();
+        assert!(calc_type("calc(1 + 2 * 3)") == Some(CalcType::Number));
+        assert!(calc_type("calc(1px + 1em)") == Some(CalcType::Length));
+        assert!(calc_type("calc(10% * 2)") == Some(CalcType::Percentage));
+        assert!(calc_type("clamp(1px, 10%, 3em)") == Some(CalcType::LengthPercentage));
+        for source in &["calc(1px + 2)", "calc(1px * 2px)", "calc(2 / 1px)", "min(1px, 2)"] {
+            assert!(calc_type(source).is_none(), "{} should be invalid", source);
+        }
+    }
+
+    #[test]
+    fn math_expressions_resolve() {
+        let ctx = LengthContext {
+            font_size: 10.0,
+            root_font_size: 16.0,
+            viewport_width: 800.0,
+            viewport_height: 600.0,
+        };
+        let resolve = |source| length(source).unwrap().resolve(&ctx);
+        assert!(resolve("calc(2em * 3)") == Value::Length(60.0, Unit::Px));
+        assert!(resolve("min(100px, 5vw)") == Value::Length(40.0, Unit::Px));
+        assert!(resolve("calc(1 + 2)") == Value::Number(3.0));
+        match resolve("calc(100% - 1em)") {
+            Value::Calc(calc) => assert_eq!(calc.evaluate(50.0), 40.0),
+            _ => panic!("a percentage should stay unresolved")
+        }
+    }
+
+    #[test]
+    fn malformed_math_expressions() {
+        for source in &["calc(1px+2px)", "calc(1px 2px)", "clamp(1px, 2px)", "calc()",
+                        "calc((1px + 2px)", "calc(1ex)", "calc(1px + auto)"] {
+            assert!(length(source).is_none(), "{} should be invalid", source);
+        }
+        let sheet = parse(String::from("p { width: calc(1px + 2); height: calc(1px+2px); \
+                                        margin-top: calc(1px + 2px); }"), Origin::Author);
+        assert_eq!(sheet.rules[0].declarations.len(), 1);
+        assert_eq!(sheet.rules[0].declarations[0].name, "margin-top");
+    }
+
+    // The names of the declarations in each rule of `source`.
+    fn declaration_names(source: &str) -> Vec<Vec<String>> {
+        parse(String::from(source), Origin::Author).rules.iter()
+            .map(|rule| rule.declarations.iter().map(|d| d.name.clone()).collect())
+            .collect()
+    }
+
+    #[test]
+    fn last_declarations_need_no_semicolon() {
+        assert_eq!(declaration_names("p { color: #fff }"), [["color"]]);
+        assert_eq!(declaration_names("p{color:#fff}a{width:1px;height:2px}"),
+                   [vec!["color"], vec!["width", "height"]]);
+    }
+
+    #[test]
+    fn comments_are_skipped() {
+        assert_eq!(declaration_names("/* header */ p { /* a */ color: /* b */ #fff /* c */; \
+                                      /* d */ width: 1px } /* footer"),
+                   [["color", "width"]]);
+    }
+
+    #[test]
+    fn malformed_declarations_are_skipped() {
+        assert_eq!(declaration_names("p { color #fff; width: 1px; : 2px; height 3px } \
+                                      a { color: red; ; width: 1px }"),
+                   [vec!["width"], vec!["color", "width"]]);
+    }
+
+    #[test]
+    fn malformed_content_functions_are_skipped() {
+        let names = declaration_names("p { content: attr(a b); } p { content: attr(a, b); } \
+                                       p { content: counter(); } p { content: counters(c); } \
+                                       p { content: counter(c,); } \
+                                       p { content: attr(a) counter(b, upper-roman) \
+                                                    counters(c, \"\"); }");
+        assert_eq!(names, [vec![], vec![], vec![], vec![], vec![], vec!["content"]]);
+    }
+
+    #[test]
+    fn malformed_track_lists_are_skipped() {
+        let invalid = ["repeat(0, 1fr)", "repeat(a, 1fr)", "repeat(2.5, 1fr)", "repeat(2 1fr)",
+                       "repeat(2, 1fr", "[a, b] 1fr", "[a", "minmax(1px)", "minmax(1px, 1fr 2fr)"];
+        for tracks in &invalid {
+            let source = format!("div {{ grid-template-columns: {}; }}", tracks);
+            assert_eq!(declaration_names(&source), [Vec::<String>::new()], "{}", tracks);
+        }
+        assert_eq!(declaration_names("div { grid-template-columns: repeat(2, [a] 1fr) \
+                                      minmax(10px, 1fr) [b c]; }"),
+                   [["grid-template-columns"]]);
+    }
+
+    #[test]
+    fn custom_properties_end_like_other_declarations() {
+        assert_eq!(declaration_names("p { --x: 1px }"), [["--x"]]);
+        assert_eq!(declaration_names("p{width:var(--x)}a{--y:{a;b};--z:2px !ie;--w:3px}"),
+                   [vec!["width"], vec!["--y", "--w"]]);
+    }
+
+    #[test]
+    fn malformed_imports_are_skipped() {
+        let imports = |source: &str| -> Vec<String> {
+            parse(String::from(source), Origin::Author).imports.iter()
+                .map(|import| import.url.clone()).collect()
+        };
+        assert_eq!(imports("@import url(a.css)"), Vec::<String>::new());
+        assert_eq!(imports("@import url(a.css"), Vec::<String>::new());
+        assert_eq!(imports("@import \"a.css"), Vec::<String>::new());
+        assert_eq!(imports("@import uri(a.css); @import url( 'b.css' ) print; \
+                            @import \"c.css\";"), ["b.css", "c.css"]);
+        assert_eq!(declaration_names("@import url(a.css) { p { color: red; } } \
+                                      p { width: 1px; }"), [["width"]]);
+    }
+
+    #[test]
+    fn unknown_annotations_are_skipped() {
+        let sheet = parse(String::from("p { color: red !ie; width: 1px ! important; \
+                                        height: 2px !; margin-top: 3px }"), Origin::Author);
+        let declarations = &sheet.rules[0].declarations;
+        let names: Vec<&str> = declarations.iter().map(|d| &*d.name).collect();
+        assert_eq!(names, ["width", "margin-top"]);
+        assert!(declarations[0].important && !declarations[1].important);
+    }
+
+    #[test]
+    fn invalid_declarations_are_skipped() {
+        let sheet = parse(String::from("p { width: 1ex; height: 90deg; margin-top: 2ch; \
+                                        padding-top: 3px; color: #fff; }"), Origin::Author);
+        let names: Vec<&str> =
+            sheet.rules[0].declarations.iter().map(|d| &*d.name).collect();
+        assert_eq!(names, ["padding-top", "color"]);
+    }
 }
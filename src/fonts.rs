@@ -0,0 +1,106 @@
+// Web fonts registered from `@font-face` rules, for the text renderer to select by
+// `font-family`.
+
+use media::{Device, MediaQueryList};
+use std::path::PathBuf;
+
+pub struct FontFace {
+    pub family: String,
+    pub weight: u16,
+    pub italic: bool,
+    pub path: PathBuf,
+    pub data: Vec<u8>, // the font file's contents
+    // The media queries of the `@import` rules the face was loaded through. The face can only
+    // be selected when they all match.
+    pub media: Vec<MediaQueryList>,
+}
+
+#[derive(Default)]
+pub struct FontRegistry {
+    pub faces: Vec<FontFace>,
+}
+
+impl FontRegistry {
+    pub fn new() -> FontRegistry {
+        Default::default()
+    }
+
+    pub fn register(&mut self, face: FontFace) {
+        self.faces.push(face);
+    }
+
+    /// Pick a face for a computed `font-family` list like `"Brand Sans", Arial, sans-serif`.
+    ///
+    /// The first family in the list with any face for `device` wins. Among its faces, one with
+    /// the requested style is preferred, then the closest weight. Returns `None` if no family in
+    /// the list is registered, in which case a built-in font should be used.
+    pub fn select(&self, families: &str, weight: u16, italic: bool, device: &Device)
+                  -> Option<&FontFace> {
+        families.split(',')
+            .map(|family| family.trim().trim_matches(|c| c == '"' || c == '\''))
+            .filter_map(|family| {
+                self.faces.iter()
+                    .filter(|face| face.family.eq_ignore_ascii_case(family))
+                    .filter(|face| face.media.iter().all(|media| media.matches(device)))
+                    .min_by_key(|face| {
+                        let weight_distance = (face.weight as i32 - weight as i32).abs();
+                        (face.italic != italic, weight_distance)
+                    })
+            })
+            .next()
+    }
+}
+
+pub fn print(registry: &FontRegistry) {
+    println!("Fonts:");
+
+    for face in &registry.faces {
+        println!("{} {}{}: {} ({} bytes)", face.family, face.weight,
+                 if face.italic { " italic" } else { "" }, face.path.display(), face.data.len());
+    }
+
+    println!();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use css::{self, Origin};
+    use media::MediaType;
+
+    fn face(family: &str, weight: u16, italic: bool, media: &str) -> FontFace {
+        let path = format!("{}-{}.woff", family, weight);
+        let import = format!("@import \"{}\" {};", path, media);
+        FontFace {
+            family: family.to_string(),
+            weight,
+            italic,
+            path: PathBuf::from(path),
+            data: Vec::new(),
+            media: css::parse(import, Origin::Author).imports.into_iter()
+                .map(|import| import.media)
+                .filter(|media| !media.queries.is_empty())
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn faces_are_selected_by_family_style_and_weight() {
+        let mut registry = FontRegistry::new();
+        registry.register(face("Brand", 400, false, ""));
+        registry.register(face("Brand", 700, false, ""));
+        registry.register(face("Brand", 300, true, ""));
+        registry.register(face("Print Only", 400, false, "print"));
+        let device = Device::new(MediaType::Screen, Default::default());
+        let select = |families, weight, italic| {
+            registry.select(families, weight, italic, &device).map(|face| face.weight)
+        };
+        assert_eq!(select("\"brand\", serif", 600, false), Some(700));
+        assert_eq!(select("Missing, 'Brand'", 350, false), Some(400));
+        assert_eq!(select("Brand", 700, true), Some(300));
+        assert_eq!(select("Print Only, Brand", 400, false), Some(400));
+        assert_eq!(select("Print Only, serif", 400, false), None);
+        let print = Device::new(MediaType::Print, Default::default());
+        assert!(registry.select("Print Only", 400, false, &print).is_some());
+    }
+}
@@ -0,0 +1,131 @@
+// Generated content: the text of `::before` and `::after` boxes, and the counters it can show.
+//
+// http://www.w3.org/TR/CSS2/generate.html
+
+use css::{ContentItem, Value};
+use dom::NodeType;
+use style::StyledNode;
+
+/// The counters in scope at some point of a walk over the style tree in document order.
+pub struct Counters {
+    counters: Vec<(String, i32)>, // innermost instances last
+}
+
+impl Counters {
+    pub fn new() -> Counters {
+        Counters { counters: Vec::new() }
+    }
+
+    /// Start the scope of a node's children. Counters reset from here on are dropped by
+    /// `leave_scope`, while those reset by the node itself stay in scope for its following
+    /// siblings.
+    pub fn enter_scope(&self) -> usize {
+        self.counters.len()
+    }
+
+    pub fn leave_scope(&mut self, scope: usize) {
+        self.counters.truncate(scope);
+    }
+
+    /// Apply the `counter-reset` and then the `counter-increment` of a node.
+    pub fn update(&mut self, node: &StyledNode) {
+        let style = &node.style().reset;
+        if let Some(Value::Counters(ref resets)) = style.counter_reset {
+            self.counters.extend(resets.iter().cloned());
+        }
+        if let Some(Value::Counters(ref increments)) = style.counter_increment {
+            for &(ref name, by) in increments {
+                match self.counters.iter_mut().rev().find(|counter| counter.0 == *name) {
+                    Some(counter) => counter.1 += by,
+                    // Incrementing a counter that isn't in scope resets it to 0 first.
+                    None => self.counters.push((name.clone(), by))
+                }
+            }
+        }
+    }
+
+    // The values of every counter named `name`, outermost first.
+    fn values(&self, name: &str) -> Vec<i32> {
+        self.counters.iter().filter(|counter| counter.0 == name).map(|counter| counter.1).collect()
+    }
+}
+
+impl Default for Counters {
+    fn default() -> Counters {
+        Counters::new()
+    }
+}
+
+/// The text generated by the `content` of a pseudo-element.
+pub fn content_text(pseudo: &StyledNode, counters: &Counters) -> String {
+    let items = match pseudo.style().reset.content {
+        Some(Value::Content(ref items)) => items,
+        _ => return String::new()
+    };
+    let mut text = String::new();
+    for item in items {
+        match *item {
+            ContentItem::Text(ref s) => text.push_str(s),
+            ContentItem::Attr(ref name) => {
+                // A missing attribute generates an empty string.
+                if let NodeType::Element(ref elem) = pseudo.node.node_type {
+                    text.push_str(elem.attributes.get(name).map_or("", |value| &**value));
+                }
+            }
+            ContentItem::Counter(ref name, ref style) => {
+                let value = counters.values(name).last().cloned().unwrap_or(0);
+                text.push_str(&format_counter(value, style));
+            }
+            ContentItem::Counters(ref name, ref separator, ref style) => {
+                let mut values = counters.values(name);
+                if values.is_empty() {
+                    values.push(0);
+                }
+                let values: Vec<String> = values.iter()
+                    .map(|&value| format_counter(value, style))
+                    .collect();
+                text.push_str(&values.join(separator));
+            }
+        }
+    }
+    text
+}
+
+// Format a counter value in a list style. Unsupported styles, and values a style can't show,
+// fall back to decimal.
+fn format_counter(value: i32, style: &str) -> String {
+    match style {
+        "none" => String::new(),
+        "lower-alpha" | "lower-latin" if value > 0 => alphabetic(value).to_lowercase(),
+        "upper-alpha" | "upper-latin" if value > 0 => alphabetic(value),
+        "lower-roman" if value > 0 && value < 4000 => roman(value).to_lowercase(),
+        "upper-roman" if value > 0 && value < 4000 => roman(value),
+        _ => value.to_string()
+    }
+}
+
+// A, B, ..., Z, AA, AB, ...
+fn alphabetic(mut value: i32) -> String {
+    let mut letters = Vec::new();
+    while value > 0 {
+        value -= 1;
+        letters.push((b'A' + (value % 26) as u8) as char);
+        value /= 26;
+    }
+    letters.iter().rev().collect()
+}
+
+fn roman(mut value: i32) -> String {
+    const NUMERALS: &[(i32, &str)] = &[
+        (1000, "M"), (900, "CM"), (500, "D"), (400, "CD"), (100, "C"), (90, "XC"),
+        (50, "L"), (40, "XL"), (10, "X"), (9, "IX"), (5, "V"), (4, "IV"), (1, "I"),
+    ];
+    let mut text = String::new();
+    for &(n, numeral) in NUMERALS {
+        while value >= n {
+            text.push_str(numeral);
+            value -= n;
+        }
+    }
+    text
+}
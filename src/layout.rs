@@ -1,13 +1,25 @@
 // box model. All sizes are in px.
 
-use style::{StyledNode, Display};
-use css::Value::{Keyword, Length};
-use css::Unit::Px;
+use style::{StyledNode, Display, TablePart};
+use css::Value::{self, Keyword, Length, Number, Calc};
+use css::Unit::{Px, Percent, Fr};
 use std::collections::VecDeque;
+use std::cmp::Ordering;
+use std::mem;
+use std::f32;
 use dom::NodeType;
+use css::PseudoElement;
+use generated::{self, Counters};
+use self::float::Floats;
 
 pub use self::BoxType::AnonymousBlock;
 
+mod flex;
+mod float;
+mod grid;
+mod position;
+mod table;
+
 #[derive(Default, Clone, Copy)]
 pub struct Dimensions {
     // Position of the content area relative to the document origin:
@@ -46,46 +58,103 @@ pub struct LayoutBox<'a> {
 pub enum BoxType<'a> {
     Vertical(&'a StyledNode<'a>),
     Horizontal(&'a StyledNode<'a>),
+    Flex(&'a StyledNode<'a>),
+    Grid(&'a StyledNode<'a>),
+    // A part of a table, with no style node if it was generated to complete a table whose
+    // parts aren't all in the document.
+    Table(TablePart, Option<&'a StyledNode<'a>>),
     AnonymousBlock,
+    // A `::before` or `::after` pseudo-element, with the text its `content` generates.
+    Generated(&'a StyledNode<'a>, String),
 }
 
 /// Transform a style tree into a layout tree.
 pub fn layout_tree<'a>(node: &'a StyledNode<'a>, mut containing_block: Dimensions) -> LayoutBox<'a> {
+    // Fixed boxes are positioned against the viewport, which is also the initial containing block.
+    let viewport = containing_block.content;
+
     // The layout algorithm expects the container height to start at 0.
     containing_block.content.height = 0.0;
 
-    let mut root_box = build_layout_tree(node);
-    root_box.layout(containing_block);
+    let mut root_box = build_layout_tree(node, &mut Counters::new());
+    root_box.layout(containing_block, Some(viewport.height), None);
+
+    // The root element establishes the initial block formatting context.
+    root_box.contain_floats();
+    root_box.calculate_block_height(viewport.width, Some(viewport.height));
+
+    root_box.layout_positioned(viewport, viewport);
     root_box
 }
 
 /// Build the tree of LayoutBoxes, but don't perform any layout calculations yet.
-fn build_layout_tree<'a>(style_node: &'a StyledNode<'a>) -> LayoutBox<'a> {
+///
+/// Boxes are built in document order, keeping track of the counters that generated content
+/// can show.
+fn build_layout_tree<'a>(style_node: &'a StyledNode<'a>, counters: &mut Counters)
+                         -> LayoutBox<'a> {
     // Create the root box.
+    counters.update(style_node);
     let mut root = LayoutBox::new(match style_node.display() {
         Display::Vertical => BoxType::Vertical(style_node),
         Display::Horizontal => BoxType::Horizontal(style_node),
+        Display::Flex => BoxType::Flex(style_node),
+        Display::Grid => BoxType::Grid(style_node),
+        Display::Table(part) => BoxType::Table(part, Some(style_node)),
         Display::None => panic!("Root node has display: none.")
     });
 
-    // Create the descendant boxes.
+    // Create the descendant boxes, with the `::before` and `::after` boxes around them.
+    let scope = counters.enter_scope();
+    if let Some(ref before) = style_node.before {
+        root.children.extend(build_generated_box(before, counters));
+    }
     for child in &style_node.children {
         match child.display() {
-            Display::Vertical => root.children.push(build_layout_tree(child)),
-            Display::Horizontal => root.children.push(build_layout_tree(child)),
+            Display::Vertical => root.children.push(build_layout_tree(child, counters)),
+            Display::Horizontal => root.children.push(build_layout_tree(child, counters)),
+            Display::Flex => root.children.push(build_layout_tree(child, counters)),
+            Display::Grid => root.children.push(build_layout_tree(child, counters)),
+            Display::Table(_) => root.children.push(build_layout_tree(child, counters)),
             Display::None => {} // Don't lay out nodes with `display: none;`
         }
     }
+    if let Some(ref after) = style_node.after {
+        root.children.extend(build_generated_box(after, counters));
+    }
+    counters.leave_scope(scope);
+
+    let children = mem::take(&mut root.children);
+    root.children = table::wrap_misparented(root.table_part(), children);
     root
 }
 
+// Build the box for a pseudo-element, unless it has `display: none`.
+fn build_generated_box<'a>(pseudo: &'a StyledNode<'a>, counters: &mut Counters)
+                           -> Option<LayoutBox<'a>> {
+    match pseudo.display() {
+        Display::None => None,
+        _ => {
+            counters.update(pseudo);
+            let text = generated::content_text(pseudo, counters);
+            Some(LayoutBox::new(BoxType::Generated(pseudo, text)))
+        }
+    }
+}
+
 impl<'a> LayoutBox<'a> {
-    /// Lay out a box and its descendants.
-    fn layout(&mut self, containing_block: Dimensions) {
+    /// Lay out a box and its descendants. `cb_height` is the height of the containing block if
+    /// it's definite. `floats` are the floats of the block formatting context the box is in, if
+    /// it's in the flow of a block container.
+    fn layout(&mut self, containing_block: Dimensions, cb_height: Option<f32>,
+              floats: Option<&mut Floats>) {
         match self.box_type {
-            BoxType::Vertical(_) => self.layout_block(containing_block),
-            BoxType::Horizontal(_) => {},
             AnonymousBlock => {}
+            BoxType::Table(_, None) => {
+                let cb = containing_block.content;
+                self.layout_sized(cb.x, cb.y + cb.height, cb.width, None, cb.width, cb_height);
+            }
+            _ => self.layout_block(containing_block, cb_height, floats)
         }
     }
 
@@ -98,7 +167,14 @@ impl<'a> LayoutBox<'a> {
     }
 
     /// Lay out a block-level element and its descendants.
-    fn layout_block(&mut self, containing_block: Dimensions) {
+    ///
+    /// The box is sized and positioned like a block whatever formatting context it establishes
+    /// for its children.
+    fn layout_block(&mut self, containing_block: Dimensions, cb_height: Option<f32>,
+                    floats: Option<&mut Floats>) {
+        // Flex containers lay out their items more than once, so start from scratch.
+        self.dimensions = Default::default();
+
         // Child width can depend on parent width, so we need to calculate this box's width before
         // laying out its children.
         self.calculate_block_width(containing_block);
@@ -106,39 +182,68 @@ impl<'a> LayoutBox<'a> {
         // Determine where the box is located within its container.
         self.calculate_block_position(containing_block);
 
-        // Recursively lay out the children of this box.
-        self.layout_block_children();
+        // Recursively lay out the children of this box. Unless the box establishes a block
+        // formatting context of its own, they flow around the floats of the one it's in.
+        let cb_width = containing_block.content.width;
+        let height = self.definite_height(cb_width, cb_height);
+        match floats {
+            Some(floats) if self.is_block_container() && !self.establishes_bfc() => {
+                self.layout_block_children(floats, height);
+            }
+            _ => self.layout_children(height)
+        }
+        if self.establishes_bfc() {
+            self.contain_floats();
+        }
 
         // Parent height can depend on child height, so `calculate_height` must be called after the
         // children are laid out.
-        self.calculate_block_height();
+        self.calculate_block_height(cb_width, cb_height);
+
+        self.offset_relative(containing_block.content.width);
     }
 
     /// Calculate the width of a block-level non-replaced element in normal flow.
     ///
     /// http://www.w3.org/TR/CSS2/visudet.html#blockwidth
     ///
-    /// Sets the horizontal padding/border dimensions, and the `width`.
+    /// Sets the horizontal padding/border dimensions, and the `width`, within `min-width` and
+    /// `max-width`.
     fn calculate_block_width(&mut self, containing_block: Dimensions) {
-        let style = self.get_style_node();
+        let cb_width = containing_block.content.width;
+        let width = self.specified_width(cb_width);
+        self.solve_block_width(containing_block, width);
+
+        // An `auto` width outside the limits is worked out again as if it were set to the limit.
+        // http://www.w3.org/TR/CSS2/visudet.html#min-max-widths
+        let (min, max) = self.width_limits(cb_width);
+        let used = self.dimensions.content.width;
+        if width.is_none() && (used > max || used < min) {
+            self.solve_block_width(containing_block, Some(used.min(max).max(min)));
+        }
+    }
+
+    /// Apply the constraints on the horizontal sizes of a block to find the ones that are
+    /// `auto`, given the width of its content area or `None` if it's `auto`.
+    fn solve_block_width(&mut self, containing_block: Dimensions, width: Option<f32>) {
+        let style = &self.get_style_node().style().reset;
 
-        // `width` has initial value `auto`.
         let auto = Keyword("auto".to_string());
-        let mut width = style.value("width").unwrap_or(auto.clone());
+        let mut width = width.map_or(auto.clone(), |width| Length(width, Px));
 
         // border, and padding have initial value 0.
         let zero = Length(0.0, Px);
 
-        let mut border_left = style.lookup("border-left-width", "border-width",
-                                       &zero);
-        let mut border_right = style.lookup("border-right-width", "border-width",
-                                        &zero);
+        let mut border_left = style.border_left_width.clone().unwrap_or(zero.clone());
+        let mut border_right = style.border_right_width.clone().unwrap_or(zero.clone());
 
-        let padding_left = style.lookup("padding-left", "padding", &zero);
-        let padding_right = style.lookup("padding-right", "padding", &zero);
+        let padding_left = style.padding_left.clone().unwrap_or(zero.clone());
+        let padding_right = style.padding_right.clone().unwrap_or(zero);
 
+        // Percentages are relative to the width of the containing block.
+        let cb_width = containing_block.content.width;
         let total: f32 = [&border_left, &border_right, &padding_left,
-                         &padding_right, &width].iter().map(|v| v.to_px()).sum();
+                         &padding_right, &width].iter().map(|v| v.to_px_of(cb_width)).sum();
 
         // If width is not auto and the total is wider than the container, treat auto margins as 0.
         if width != auto && total > containing_block.content.width {
@@ -184,10 +289,10 @@ impl<'a> LayoutBox<'a> {
             }
         }
         let d = &mut self.dimensions;
-        d.content.width = width.to_px();
+        d.content.width = width.to_px_of(cb_width);
 
-        d.padding.left = padding_left.to_px();
-        d.padding.right = padding_right.to_px();
+        d.padding.left = padding_left.to_px_of(cb_width);
+        d.padding.right = padding_right.to_px_of(cb_width);
 
         d.border.left = border_left.to_px();
         d.border.right = border_right.to_px();
@@ -199,17 +304,19 @@ impl<'a> LayoutBox<'a> {
     ///
     /// Sets the vertical padding/border dimensions, and the `x`, `y` values.
     fn calculate_block_position(&mut self, containing_block: Dimensions) {
-        let style = self.get_style_node();
+        let style = &self.get_style_node().style().reset;
         let d = &mut self.dimensions;
 
         // border, and padding have initial value 0.
         let zero = Length(0.0, Px);
 
-        d.border.top = style.lookup("border-top-width", "border-width", &zero).to_px();
-        d.border.bottom = style.lookup("border-bottom-width", "border-width", &zero).to_px();
+        d.border.top = style.border_top_width.as_ref().unwrap_or(&zero).to_px();
+        d.border.bottom = style.border_bottom_width.as_ref().unwrap_or(&zero).to_px();
 
-        d.padding.top = style.lookup("padding-top", "padding", &zero).to_px();
-        d.padding.bottom = style.lookup("padding-bottom", "padding", &zero).to_px();
+        // Vertical padding percentages also refer to the containing block's width.
+        let cb_width = containing_block.content.width;
+        d.padding.top = style.padding_top.as_ref().unwrap_or(&zero).to_px_of(cb_width);
+        d.padding.bottom = style.padding_bottom.as_ref().unwrap_or(&zero).to_px_of(cb_width);
 
         d.content.x = containing_block.content.x + d.border.left + d.padding.left;
 
@@ -218,35 +325,397 @@ impl<'a> LayoutBox<'a> {
                       d.border.top + d.padding.top;
     }
 
+    /// Lay out the box with its border box at `(x, y)` and `width` wide, whatever its own `width`
+    /// says, and `height` tall if given. For formatting contexts that size their children
+    /// themselves.
+    fn layout_sized(&mut self, x: f32, y: f32, width: f32, height: Option<f32>,
+                    cb_width: f32, cb_height: Option<f32>) {
+        if let AnonymousBlock = self.box_type {
+            return;
+        }
+        self.dimensions = Default::default();
+        self.calculate_edges(cb_width);
+        self.layout_within_edges(x, y, width, height, cb_width, cb_height);
+        self.offset_relative(cb_width);
+    }
+
+    /// Like `layout_sized`, for a box whose padding and border sizes are already set.
+    fn layout_within_edges(&mut self, x: f32, y: f32, width: f32, height: Option<f32>,
+                           cb_width: f32, cb_height: Option<f32>) {
+        let edges = self.dimensions.border_box();
+        let inner_height = height.map(|height| (height - edges.height).max(0.0));
+        {
+            let d = &mut self.dimensions;
+            d.content.x = x + d.border.left + d.padding.left;
+            d.content.y = y + d.border.top + d.padding.top;
+            d.content.width = (width - edges.width).max(0.0);
+        }
+
+        let definite = inner_height.or(self.definite_height(cb_width, cb_height));
+        self.layout_children(definite);
+        match inner_height {
+            Some(height) => self.dimensions.content.height = height,
+            None => {
+                // Boxes laid out this way are in formatting contexts of their own.
+                self.contain_floats();
+                self.calculate_block_height(cb_width, cb_height);
+            }
+        }
+    }
+
+    /// Set the padding and border sizes from the style alone, with percentages of `cb_width`.
+    ///
+    /// Auto borders, which stand in for margins, count as zero.
+    fn calculate_edges(&mut self, cb_width: f32) {
+        let style = match self.style_node() {
+            Some(node) => &node.style().reset,
+            None => {
+                self.dimensions.padding = Default::default();
+                self.dimensions.border = Default::default();
+                return;
+            }
+        };
+        let px = |value: &Option<Value>| match *value {
+            Some(ref value @ Length(..)) | Some(ref value @ Calc(_)) => value.to_px_of(cb_width),
+            _ => 0.0
+        };
+        let d = &mut self.dimensions;
+        d.padding = EdgeSizes {
+            left: px(&style.padding_left),
+            right: px(&style.padding_right),
+            top: px(&style.padding_top),
+            bottom: px(&style.padding_bottom),
+        };
+        d.border = EdgeSizes {
+            left: px(&style.border_left_width),
+            right: px(&style.border_right_width),
+            top: px(&style.border_top_width),
+            bottom: px(&style.border_bottom_width),
+        };
+    }
+
     fn get_style_node(&self) -> &'a StyledNode<'a> {
+        self.style_node().expect("Anonymous box has no style node")
+    }
+
+    /// The style node of the box, or `None` for an anonymous box.
+    pub fn style_node(&self) -> Option<&'a StyledNode<'a>> {
+        match self.box_type {
+            BoxType::Vertical(node) | BoxType::Horizontal(node) | BoxType::Flex(node) |
+            BoxType::Grid(node) => Some(node),
+            BoxType::Generated(node, _) => Some(node),
+            BoxType::Table(_, node) => node,
+            AnonymousBlock => None
+        }
+    }
+
+    fn table_part(&self) -> Option<TablePart> {
+        match self.box_type {
+            BoxType::Table(part, _) => Some(part),
+            _ => None
+        }
+    }
+
+    /// Lay out the box's children within its content area, by the rules of the formatting
+    /// context it establishes. `height` is the height of the content area if it's known before
+    /// the children are laid out.
+    fn layout_children(&mut self, height: Option<f32>) {
+        if self.is_block_container() {
+            return self.layout_block_children(&mut Floats::new(), height);
+        }
+
+        // Absolutely positioned children take no part in other formatting contexts, and start out
+        // at the top left of the content area.
+        let mut out_of_flow = Vec::new();
+        let mut i = 0;
+        while i < self.children.len() {
+            if self.children[i].is_out_of_flow() {
+                let mut child = self.children.remove(i);
+                child.dimensions = Default::default();
+                child.dimensions.content.x = self.dimensions.content.x;
+                child.dimensions.content.y = self.dimensions.content.y;
+                out_of_flow.push((i + out_of_flow.len(), child));
+            } else {
+                i += 1;
+            }
+        }
+
         match self.box_type {
-            BoxType::Vertical(node) | BoxType::Horizontal(node) => node,
-            BoxType::Horizontal(node) => node,
-            AnonymousBlock => panic!("Anonymous block box has no style node")
+            BoxType::Horizontal(_) => self.layout_horizontal_children(height),
+            BoxType::Flex(_) => self.layout_flex_items(height),
+            BoxType::Grid(_) => self.layout_grid_items(height),
+            BoxType::Table(TablePart::Table, _) => self.layout_table(height),
+            // Rows and row groups are laid out by their table.
+            _ => {}
+        }
+
+        for (i, child) in out_of_flow {
+            self.children.insert(i, child);
         }
     }
 
-    /// Lay out the block's children within its content area.
+    /// Whether the box lays out its children as blocks. Table cells and captions lay out their
+    /// contents like blocks.
+    fn is_block_container(&self) -> bool {
+        matches!(self.box_type,
+                 BoxType::Vertical(_) | BoxType::Generated(..) |
+                 BoxType::Table(TablePart::Cell, _) | BoxType::Table(TablePart::Caption, _))
+    }
+
+    /// Lay out the block's children within its content area, among the `floats` of the block
+    /// formatting context it's in. `height` is the height of the content area if it's definite.
     ///
-    /// Sets `self.dimensions.height` to the total content height.
-    fn layout_block_children(&mut self) {
+    /// Floating children are as wide as their `width` says, or as the content area if it's
+    /// `auto`, and don't add to its height. Sets `self.dimensions.height` to the total content
+    /// height.
+    fn layout_block_children(&mut self, floats: &mut Floats, height: Option<f32>) {
         let d = &mut self.dimensions;
         for child in &mut self.children {
-            child.layout(*d);
+            if child.is_out_of_flow() {
+                // Leave it at its static position until it's laid out.
+                child.dimensions = Default::default();
+                child.dimensions.content.x = d.content.x;
+                child.dimensions.content.y = d.content.y + d.content.height;
+                continue;
+            }
+
+            let y = floats.clear(child.clear(), d.content.y + d.content.height);
+            if let Some(side) = child.float_side() {
+                let width = child.fixed_outer_width(d.content.width).unwrap_or(d.content.width);
+                child.layout_sized(0.0, 0.0, width, None, d.content.width, height);
+                let outer_height = child.dimensions.border_box().height;
+                let (x, y) = floats.place(side, d.content, y, width, outer_height);
+                child.translate(x, y);
+                continue;
+            }
+
+            // Clearance adds to the height above the child.
+            d.content.height = y - d.content.y;
+            let mut containing_block = *d;
+            if child.establishes_bfc() || child.holds_text() {
+                // Fit the child in beside the floats.
+                let width = child.fixed_outer_width(d.content.width).unwrap_or(0.0);
+                let (top, left, right) = floats.fit(d.content, y, width, 0.0);
+                containing_block.content.x = left;
+                containing_block.content.width = right - left;
+                containing_block.content.height = top - d.content.y;
+                d.content.height = top - d.content.y;
+                child.layout(containing_block, height, None);
+            } else {
+                child.layout(containing_block, height, Some(floats));
+            }
             // Increment the height so each child is laid out below the previous one.
             d.content.height = d.content.height +
                 child.dimensions.border_box().height;
         }
     }
 
+    /// Lay out the box's children from left to right across its content area.
+    ///
+    /// Children with a set width keep it, and those with `width: auto` share the remaining space
+    /// equally. The content area is the children's containing block, whatever slot they're in.
+    /// Sets `self.dimensions.height` to the height of the tallest child. `height` is the height
+    /// of the content area if it's definite.
+    fn layout_horizontal_children(&mut self, height: Option<f32>) {
+        let d = &mut self.dimensions;
+        let widths: Vec<Option<f32>> = self.children.iter()
+            .map(|child| child.fixed_outer_width(d.content.width))
+            .collect();
+        let fixed: f32 = widths.iter().filter_map(|&width| width).sum();
+        let autos = widths.iter().filter(|width| width.is_none()).count();
+        let share = (d.content.width - fixed).max(0.0) / autos.max(1) as f32;
+
+        let mut x = d.content.x;
+        for (child, width) in self.children.iter_mut().zip(widths) {
+            let width = width.unwrap_or(share);
+            child.layout_sized(x, d.content.y, width, None, d.content.width, height);
+
+            x += width;
+            d.content.height = d.content.height.max(child.dimensions.border_box().height);
+        }
+    }
+
+    /// The width of the box's border box plus margins, or `None` if its width is `auto`.
+    ///
+    /// Auto margins count as zero.
+    fn fixed_outer_width(&self, cb_width: f32) -> Option<f32> {
+        let style = &self.style_node()?.style().reset;
+        let width = self.specified_width(cb_width)?;
+        let edges = [&style.border_left_width, &style.border_right_width,
+                     &style.padding_left, &style.padding_right];
+        Some(width + edges.iter().map(|edge| match **edge {
+            Some(ref edge @ Length(..)) | Some(ref edge @ Calc(_)) => edge.to_px_of(cb_width),
+            _ => 0.0
+        }).sum::<f32>())
+    }
+
     /// Height of a block-level non-replaced element in normal flow with overflow visible.
-    fn calculate_block_height(&mut self) {
+    fn calculate_block_height(&mut self, cb_width: f32, cb_height: Option<f32>) {
         // If the height is set to an explicit length, use that exact length.
-        // Otherwise, just keep the value set by `layout_block_children`.
-        if let Some(Length(h, Px)) = self.get_style_node().value("height") {
-            self.dimensions.content.height = h;
+        // Otherwise, just keep the value set by `layout_children`, within the limits.
+        match self.definite_height(cb_width, cb_height) {
+            Some(height) => self.dimensions.content.height = height,
+            None => {
+                let (min, max) = self.height_limits(cb_width, cb_height);
+                let content = &mut self.dimensions.content;
+                content.height = content.height.min(max).max(min);
+            }
         }
     }
+
+    /// The height of the box's content area if `height` sets it, within `min-height` and
+    /// `max-height`, with percentages of `cb_height`.
+    ///
+    /// Percentage heights, including math expressions with percentages, behave as `auto` if
+    /// the containing block's height isn't definite.
+    fn definite_height(&self, cb_width: f32, cb_height: Option<f32>) -> Option<f32> {
+        let height = definite(&self.style_node()?.style().reset.height, cb_height)?;
+        let (min, max) = self.height_limits(cb_width, cb_height);
+        Some((height - self.vertical_box_sizing(cb_width)).max(0.0).min(max).max(min))
+    }
+
+    /// The width of the box's content area if `width` sets it, within `min-width` and
+    /// `max-width`, with percentages of `cb_width`.
+    fn specified_width(&self, cb_width: f32) -> Option<f32> {
+        let width = definite(&self.style_node()?.style().reset.width, Some(cb_width))?;
+        let (min, max) = self.width_limits(cb_width);
+        Some((width - self.horizontal_box_sizing(cb_width)).max(0.0).min(max).max(min))
+    }
+
+    /// The smallest and largest widths of the box's content area that `min-width` and
+    /// `max-width` allow, with percentages of `cb_width`.
+    fn width_limits(&self, cb_width: f32) -> (f32, f32) {
+        let style = match self.style_node() {
+            Some(node) => &node.style().reset,
+            None => return (0.0, f32::INFINITY)
+        };
+        let edges = self.horizontal_box_sizing(cb_width);
+        let limit = |value| definite(value, Some(cb_width)).map(|limit| (limit - edges).max(0.0));
+        (limit(&style.min_width).unwrap_or(0.0), limit(&style.max_width).unwrap_or(f32::INFINITY))
+    }
+
+    /// The smallest and largest heights of the box's content area that `min-height` and
+    /// `max-height` allow, with percentages of `cb_height`. Percentages don't limit it if the
+    /// containing block's height isn't definite.
+    fn height_limits(&self, cb_width: f32, cb_height: Option<f32>) -> (f32, f32) {
+        let style = match self.style_node() {
+            Some(node) => &node.style().reset,
+            None => return (0.0, f32::INFINITY)
+        };
+        let edges = self.vertical_box_sizing(cb_width);
+        let limit = |value| definite(value, cb_height).map(|limit| (limit - edges).max(0.0));
+        (limit(&style.min_height).unwrap_or(0.0), limit(&style.max_height).unwrap_or(f32::INFINITY))
+    }
+
+    /// The horizontal padding and borders that the box's sizes include, with percentages of
+    /// `cb_width`: all of them with `box-sizing: border-box`, and none otherwise.
+    ///
+    /// Auto borders, which stand in for margins, count as zero.
+    fn horizontal_box_sizing(&self, cb_width: f32) -> f32 {
+        let style = match self.style_node() {
+            Some(node) => &node.style().reset,
+            None => return 0.0
+        };
+        if keyword(&style.box_sizing, "content-box") != "border-box" {
+            return 0.0;
+        }
+        [&style.padding_left, &style.padding_right,
+         &style.border_left_width, &style.border_right_width].iter()
+            .map(|edge| definite(edge, Some(cb_width)).unwrap_or(0.0))
+            .sum()
+    }
+
+    /// The vertical padding and borders that the box's sizes include, like
+    /// `horizontal_box_sizing`. Vertical padding percentages also refer to `cb_width`.
+    fn vertical_box_sizing(&self, cb_width: f32) -> f32 {
+        let style = match self.style_node() {
+            Some(node) => &node.style().reset,
+            None => return 0.0
+        };
+        if keyword(&style.box_sizing, "content-box") != "border-box" {
+            return 0.0;
+        }
+        [&style.padding_top, &style.padding_bottom,
+         &style.border_top_width, &style.border_bottom_width].iter()
+            .map(|edge| definite(edge, Some(cb_width)).unwrap_or(0.0))
+            .sum()
+    }
+
+    /// Sort the children of a flex or grid container into the order they're laid out in: by
+    /// `order`, and in document order within the same `order`.
+    fn sort_children_by_order(&mut self) {
+        let order = |child: &LayoutBox| match child.style_node() {
+            Some(node) => number(&node.style().reset.order, 0.0),
+            None => 0.0
+        };
+        self.children.sort_by(|a, b| order(a).partial_cmp(&order(b)).unwrap_or(Ordering::Equal));
+    }
+
+    /// Move the box and all its descendants.
+    fn translate(&mut self, dx: f32, dy: f32) {
+        self.dimensions.content.x += dx;
+        self.dimensions.content.y += dy;
+        for child in &mut self.children {
+            child.translate(dx, dy);
+        }
+    }
+}
+
+// The offset of the first of `count` boxes and the extra space between each of them, when
+// `free` space is distributed by a `justify-content` or `align-content` value.
+fn distribute(value: &str, free: f32, count: usize) -> (f32, f32) {
+    let n = count as f32;
+    match value {
+        "flex-end" | "end" | "right" => (free, 0.0),
+        "center" => (free / 2.0, 0.0),
+        "space-between" if free > 0.0 && count > 1 => (0.0, free / (n - 1.0)),
+        "space-around" if free > 0.0 => (free / n / 2.0, free / n),
+        "space-evenly" if free > 0.0 => (free / (n + 1.0), free / (n + 1.0)),
+        // Without space to spare, boxes spaced around or evenly are centered.
+        "space-around" | "space-evenly" => (free / 2.0, 0.0),
+        _ => (0.0, 0.0)
+    }
+}
+
+// The total size of the gaps between `count` boxes.
+fn spacing(gap: f32, count: usize) -> f32 {
+    gap * count.saturating_sub(1) as f32
+}
+
+fn keyword<'v>(value: &'v Option<Value>, initial: &'v str) -> &'v str {
+    match *value {
+        Some(Keyword(ref k)) => k,
+        _ => initial
+    }
+}
+
+fn number(value: &Option<Value>, initial: f32) -> f32 {
+    match *value {
+        Some(Number(n)) => n,
+        _ => initial
+    }
+}
+
+// A gap of `normal` is zero, as is a percentage of a size that isn't known.
+fn gap(value: &Option<Value>, reference: f32) -> f32 {
+    match *value {
+        Some(ref gap @ Length(..)) | Some(ref gap @ Calc(_)) => gap.to_px_of(reference),
+        _ => 0.0
+    }
+}
+
+// A size in px, if it's a length, or a percentage or math expression of a known `reference`.
+// A zero length can be written without a unit.
+fn definite(value: &Option<Value>, reference: Option<f32>) -> Option<f32> {
+    match (value, reference) {
+        (&Some(Number(0.0)), _) => Some(0.0),
+        (&Some(Length(_, Fr)), _) => None,
+        (&Some(Length(_, Percent)), None) | (&Some(Calc(_)), None) => None,
+        (&Some(ref size @ Length(..)), _) | (&Some(ref size @ Calc(_)), _) => {
+            Some(size.to_px_of(reference.unwrap_or(0.0)))
+        }
+        _ => None
+    }
 }
 
 impl Rect {
@@ -292,7 +761,8 @@ pub fn print(root_node: LayoutBox) {
 
         // print node info
         match current.lbnode.box_type {
-            BoxType::Vertical(sn) => {
+            BoxType::Vertical(sn) | BoxType::Horizontal(sn) | BoxType::Flex(sn) |
+            BoxType::Grid(sn) | BoxType::Table(_, Some(sn)) => {
                 let name = match sn.node.node_type {
                     NodeType::Element(ref e) => ("elem", &e.tag_name),
                     NodeType::Text(ref s) => ("txt", s),
@@ -303,6 +773,31 @@ pub fn print(root_node: LayoutBox) {
                        current.lbnode.dimensions.content.width,
                        current.lbnode.dimensions.content.height);
             }
+            BoxType::Generated(sn, ref text) => {
+                let name = match sn.pseudo {
+                    Some(PseudoElement::Before) => "::before",
+                    _ => "::after"
+                };
+                print!("{}: \"{}\" -- ({},{}) [{},{}]", name, text,
+                       current.lbnode.dimensions.content.x,
+                       current.lbnode.dimensions.content.y,
+                       current.lbnode.dimensions.content.width,
+                       current.lbnode.dimensions.content.height);
+            }
+            BoxType::Table(part, None) => {
+                let name = match part {
+                    TablePart::Table => "table",
+                    TablePart::RowGroup => "table-row-group",
+                    TablePart::Row => "table-row",
+                    TablePart::Cell => "table-cell",
+                    TablePart::Caption => "table-caption",
+                };
+                print!("anon: {} -- ({},{}) [{},{}]", name,
+                       current.lbnode.dimensions.content.x,
+                       current.lbnode.dimensions.content.y,
+                       current.lbnode.dimensions.content.width,
+                       current.lbnode.dimensions.content.height);
+            }
             _ => ()
         }
 
@@ -324,3 +819,107 @@ pub fn print(root_node: LayoutBox) {
 
     println!("");
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use css::{self, Origin};
+    use html;
+    use media::{Device, MediaType};
+    use style;
+
+    // Lay out `source` styled by `css` in an 800px by 600px viewport, and pass the root box to
+    // `f`.
+    pub fn lay_out<F: FnOnce(&LayoutBox)>(source: &str, css: &str, f: F) {
+        let root = html::parse(String::from(source));
+        let sheets = [css::parse(String::from(css), Origin::Author)];
+        let mut viewport: Dimensions = Default::default();
+        viewport.content.width = 800.0;
+        viewport.content.height = 600.0;
+        let device = Device::new(MediaType::Screen, viewport);
+        let styled = style::style_tree(&root, &sheets, &device);
+        f(&layout_tree(&styled, viewport));
+    }
+
+    // One side or size of the content area of each child of `parent`.
+    pub fn children<F: Fn(&Rect) -> f32>(parent: &LayoutBox, f: F) -> Vec<f32> {
+        parent.children.iter().map(|child| f(&child.dimensions.content)).collect()
+    }
+
+    pub fn assert_near(actual: &[f32], expected: &[f32]) {
+        assert!(actual.len() == expected.len() &&
+                actual.iter().zip(expected).all(|(a, e)| (a - e).abs() < 0.01),
+                "{:?} isn't {:?}", actual, expected);
+    }
+
+    #[test]
+    fn horizontal_children_share_the_width_left_over() {
+        lay_out("<div><p id=\"a\"></p><p></p><p></p></div>",
+                "* { display: block; } div { display: horizontal; width: 600px; } \
+                 #a { width: 100px; padding: 0 10px; }", |root| {
+            assert_near(&children(root, |c| c.width), &[100.0, 240.0, 240.0]);
+            assert_near(&children(root, |c| c.x), &[10.0, 120.0, 360.0]);
+        });
+    }
+
+    #[test]
+    fn horizontal_children_resolve_percentages_against_the_container() {
+        lay_out("<div><p></p><p></p></div>",
+                "* { display: block; } div { display: horizontal; } p { width: 50%; }", |root| {
+            assert_near(&children(root, |c| c.width), &[400.0, 400.0]);
+            assert_near(&children(root, |c| c.x), &[0.0, 400.0]);
+        });
+        lay_out("<div><p></p><p id=\"a\"></p></div>",
+                "* { display: block; } div { display: horizontal; } p { width: 25%; } \
+                 #a { width: auto; padding-left: 10%; }", |root| {
+            assert_near(&children(root, |c| c.width), &[200.0, 520.0]);
+            assert_near(&children(root, |c| c.x), &[0.0, 280.0]);
+        });
+    }
+
+    #[test]
+    fn horizontal_rows_are_as_tall_as_their_tallest_child() {
+        lay_out("<div><p></p><p id=\"a\"></p><p></p></div>",
+                "* { display: block; } div { display: horizontal; } p { height: 10px; } \
+                 #a { height: 30px; border-bottom-width: 5px; }", |root| {
+            assert_near(&[root.dimensions.content.height], &[35.0]);
+            assert_near(&children(root, |c| c.height), &[10.0, 30.0, 10.0]);
+        });
+    }
+
+    #[test]
+    fn percentage_heights_need_a_definite_containing_block() {
+        let css = "* { display: block; } #a { height: 200px; } p { height: 50%; } \
+                   p + p { height: calc(50% + 10px); }";
+        lay_out("<div><div id=\"a\"><p></p><p></p></div><div><p></p><p></p></div></div>", css,
+                |root| {
+            assert_near(&children(&root.children[0], |c| c.height), &[100.0, 110.0]);
+            assert_near(&children(&root.children[1], |c| c.height), &[0.0, 0.0]);
+        });
+        lay_out("<div></div>", "div { display: block; height: 50%; }", |root| {
+            assert_near(&[root.dimensions.content.height], &[300.0]);
+        });
+    }
+
+    #[test]
+    fn percentage_height_limits_need_a_definite_containing_block() {
+        let css = "* { display: block; } #a { height: 200px; } \
+                   p { height: 150px; max-height: 50%; } p + p { height: auto; min-height: 25%; }";
+        lay_out("<div><div id=\"a\"><p></p><p></p></div><div><p></p><p></p></div></div>", css,
+                |root| {
+            assert_near(&children(&root.children[0], |c| c.height), &[100.0, 50.0]);
+            assert_near(&children(&root.children[1], |c| c.height), &[150.0, 0.0]);
+        });
+    }
+
+    #[test]
+    fn border_box_sizes_include_padding_within_limits() {
+        lay_out("<div><p></p></div>",
+                "* { display: block; } div { width: 400px; } \
+                 p { box-sizing: border-box; width: 300px; max-width: 200px; padding: 10% 20px; \
+                 height: 100px; }", |root| {
+            assert_near(&children(root, |c| c.width), &[160.0]);
+            assert_near(&children(root, |c| c.height), &[20.0]);
+        });
+    }
+}
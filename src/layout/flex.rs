@@ -0,0 +1,396 @@
+// Flex layout: a formatting context that places its children in lines along a main axis,
+// flexes their sizes to fill each line, and aligns them across it.
+//
+// https://www.w3.org/TR/css-flexbox-1/
+//
+// There's no inline layout to measure content with, so the content size of an item along a row
+// is the width it would take as a block: the whole of the container.
+
+use super::{LayoutBox, keyword, number, gap, definite, distribute, spacing};
+use css::Value::Keyword;
+use properties::ResetStyle;
+use std::f32;
+
+// A flex item during layout. Outer sizes are of the border box, which includes the margins
+// that borders stand in for.
+struct Item {
+    base: f32,       // flex base size, of the content box
+    main_edges: f32, // padding and borders along the main axis
+    grow: f32,
+    shrink: f32,
+    target: f32,     // flexed size of the content box along the main axis
+    min: f32,        // smallest and largest sizes of the content box along the main axis
+    max: f32,
+    frozen: bool,
+    cross: f32,      // outer size along the cross axis, before stretching
+    stretch: bool,   // whether the item stretches to the cross size of its line
+    align: String,   // `align-self`, with `auto` resolved
+}
+
+impl Item {
+    fn outer_base(&self) -> f32 {
+        self.base + self.main_edges
+    }
+
+    // The outer size of the item before it flexes: its base size within its limits.
+    fn outer_hypothetical(&self) -> f32 {
+        self.clamp(self.base) + self.main_edges
+    }
+
+    fn outer_target(&self) -> f32 {
+        self.target + self.main_edges
+    }
+
+    // Sizes can't be negative, or break the item's `min-*` and `max-*` limits.
+    fn clamp(&self, size: f32) -> f32 {
+        size.min(self.max).max(self.min).max(0.0)
+    }
+}
+
+impl<'a> LayoutBox<'a> {
+    /// Lay out the box's children as flex items. `height` is the height of the content area if
+    /// it's known up front.
+    ///
+    /// https://www.w3.org/TR/css-flexbox-1/#layout-algorithm
+    ///
+    /// Sets `self.dimensions.height` to the height the lines take up.
+    pub(super) fn layout_flex_items(&mut self, height: Option<f32>) {
+        let style = &self.get_style_node().style().reset;
+        let direction = keyword(&style.flex_direction, "row");
+        let row = !direction.starts_with("column");
+        let main_reverse = direction.ends_with("-reverse");
+        let wrap = keyword(&style.flex_wrap, "nowrap") != "nowrap";
+        let cross_reverse = keyword(&style.flex_wrap, "nowrap") == "wrap-reverse";
+
+        let content = self.dimensions.content;
+        let (inner_main, inner_cross) = if row {
+            (Some(content.width), height)
+        } else {
+            (height, Some(content.width))
+        };
+        let row_gap = gap(&style.row_gap, height.unwrap_or(0.0));
+        let column_gap = gap(&style.column_gap, content.width);
+        let (main_gap, cross_gap) = if row { (column_gap, row_gap) } else { (row_gap, column_gap) };
+
+        self.sort_children_by_order();
+
+        let align_items = keyword(&style.align_items, "normal");
+        let mut items: Vec<Item> = self.children.iter_mut()
+            .map(|child| child.flex_item(row, inner_main, content.width, align_items))
+            .collect();
+
+        // Collect the items into lines, breaking before any that would overflow the container.
+        let available = inner_main.unwrap_or(f32::INFINITY);
+        let mut lines = Vec::new();
+        let mut start = 0;
+        let mut used = 0.0;
+        for (i, item) in items.iter().enumerate() {
+            if i == start {
+                used = item.outer_hypothetical();
+            } else if wrap && used + main_gap + item.outer_hypothetical() > available {
+                lines.push((start, i));
+                start = i;
+                used = item.outer_hypothetical();
+            } else {
+                used += main_gap + item.outer_hypothetical();
+            }
+        }
+        if start < items.len() {
+            lines.push((start, items.len()));
+        }
+
+        for &(start, end) in &lines {
+            resolve_flexible_lengths(&mut items[start..end], available, main_gap);
+        }
+
+        // Find the height of each item in a row by laying it out at its flexed width.
+        if row {
+            for (child, item) in self.children.iter_mut().zip(items.iter_mut()) {
+                child.layout_sized(0.0, 0.0, item.outer_target(), None, content.width, height);
+                item.cross = child.dimensions.border_box().height;
+            }
+        }
+
+        // A single line fills a container whose cross size is known; otherwise a line is as
+        // thick as its thickest item.
+        let mut line_cross: Vec<f32> = lines.iter()
+            .map(|&(start, end)| {
+                items[start..end].iter().map(|item| item.cross).fold(0.0, f32::max)
+            })
+            .collect();
+        if let (false, Some(cross), true) = (wrap, inner_cross, !lines.is_empty()) {
+            line_cross[0] = cross;
+        }
+
+        // Distribute the leftover cross space between the lines with `align-content`.
+        let (mut cross_pos, mut between) = (0.0, 0.0);
+        if let (true, Some(cross)) = (wrap, inner_cross) {
+            let free = cross - line_cross.iter().sum::<f32>() - spacing(cross_gap, lines.len());
+            match keyword(&style.align_content, "normal") {
+                "normal" | "stretch" => if free > 0.0 {
+                    for line in &mut line_cross {
+                        *line += free / lines.len() as f32;
+                    }
+                },
+                value => {
+                    let (offset, extra) = distribute(value, free, lines.len());
+                    cross_pos = offset;
+                    between = extra;
+                }
+            }
+        }
+        let cross_extent = inner_cross.unwrap_or(
+            line_cross.iter().sum::<f32>() + spacing(cross_gap, lines.len()));
+        let main_extent = inner_main.unwrap_or(lines.iter().map(|&(start, end)| {
+            items[start..end].iter().map(Item::outer_target).sum::<f32>() +
+                spacing(main_gap, end - start)
+        }).fold(0.0, f32::max));
+
+        // Place the items along each line with `justify-content`, and across it with
+        // `align-self`.
+        let justify_content = keyword(&style.justify_content, "normal");
+        for (&(start, end), &line) in lines.iter().zip(&line_cross) {
+            let used = items[start..end].iter().map(Item::outer_target).sum::<f32>() +
+                       spacing(main_gap, end - start);
+            let free = main_extent - used;
+            let (mut main_pos, extra) = distribute(justify_content, free, end - start);
+
+            for (child, item) in self.children[start..end].iter_mut().zip(&items[start..end]) {
+                let main_size = item.outer_target();
+                let cross_size = if item.stretch { line } else { item.cross };
+                let offset = match &*item.align {
+                    "flex-end" | "end" | "self-end" => line - cross_size,
+                    "center" => (line - cross_size) / 2.0,
+                    _ => 0.0
+                };
+                let main_start = if main_reverse {
+                    main_extent - main_pos - main_size
+                } else {
+                    main_pos
+                };
+                let cross_start = if cross_reverse {
+                    cross_extent - cross_pos - offset - cross_size
+                } else {
+                    cross_pos + offset
+                };
+
+                if row {
+                    let height = if item.stretch { Some(cross_size) } else { None };
+                    child.layout_sized(content.x + main_start, content.y + cross_start,
+                                       main_size, height, content.width, inner_cross);
+                } else {
+                    child.layout_sized(content.x + cross_start, content.y + main_start,
+                                       cross_size, Some(main_size), content.width, inner_main);
+                }
+                main_pos += main_size + main_gap + extra;
+            }
+            cross_pos += line + cross_gap + between;
+        }
+
+        self.dimensions.content.height = if row { cross_extent } else { main_extent };
+    }
+
+    // Find the flex base size of an item and how it flexes and aligns.
+    //
+    // https://www.w3.org/TR/css-flexbox-1/#algo-main-item
+    fn flex_item(&mut self, row: bool, inner_main: Option<f32>, cb_width: f32, align_items: &str)
+                 -> Item {
+        // Anonymous items have the initial value of every property.
+        let initial = ResetStyle::default();
+        let style = match self.style_node() {
+            Some(node) => &node.style().reset,
+            None => &initial
+        };
+        self.dimensions = Default::default();
+        self.calculate_edges(cb_width);
+        let edges = self.dimensions.border_box();
+        let main_edges = if row { edges.width } else { edges.height };
+        let (main_size, cross_size) = if row {
+            (&style.width, &style.height)
+        } else {
+            (&style.height, &style.width)
+        };
+
+        // A basis of `auto` defers to the main size property, and a basis or size that can't be
+        // resolved falls back to the size of the content. Either one follows `box-sizing`.
+        let basis = match style.flex_basis {
+            None => definite(main_size, inner_main),
+            Some(Keyword(ref k)) if k == "auto" => definite(main_size, inner_main),
+            ref basis => definite(basis, inner_main)
+        };
+        let (sizing, (min, max)) = if row {
+            (self.horizontal_box_sizing(cb_width), self.width_limits(cb_width))
+        } else {
+            (self.vertical_box_sizing(cb_width), self.height_limits(cb_width, inner_main))
+        };
+        let basis = basis.map(|basis| (basis - sizing).max(0.0));
+        // The width of an item in a column doesn't depend on how the column flexes.
+        let cross = self.specified_width(cb_width).map_or(cb_width, |w| w + edges.width);
+        let base = match basis {
+            Some(basis) => basis,
+            None if row => (cb_width - main_edges).max(0.0),
+            None => {
+                self.layout_sized(0.0, 0.0, cross, None, cb_width, inner_main);
+                self.dimensions.content.height
+            }
+        };
+
+        let align = match keyword(&style.align_self, "auto") {
+            "auto" => align_items,
+            align => align
+        };
+        let align = if align == "normal" { "stretch" } else { align };
+        Item {
+            base,
+            main_edges,
+            grow: number(&style.flex_grow, 0.0),
+            shrink: number(&style.flex_shrink, 1.0),
+            target: base,
+            min,
+            max,
+            frozen: false,
+            cross,
+            stretch: align == "stretch" && definite(cross_size, None).is_none(),
+            align: String::from(align),
+        }
+    }
+}
+
+// Grow or shrink the items of a line so that they fill `available` space, freezing any item that
+// can't flex any further.
+//
+// https://www.w3.org/TR/css-flexbox-1/#resolve-flexible-lengths
+fn resolve_flexible_lengths(items: &mut [Item], available: f32, gap: f32) {
+    let gaps = spacing(gap, items.len());
+    let hypothetical: f32 = items.iter().map(Item::outer_hypothetical).sum();
+    let growing = available - hypothetical - gaps > 0.0;
+
+    // Items that can't flex the way the line needs to, including those their limits already
+    // hold back, stay at their hypothetical size.
+    for item in items.iter_mut() {
+        item.target = item.clamp(item.base);
+        item.frozen = !available.is_finite() ||
+                      if growing {
+                          item.grow == 0.0 || item.base > item.target
+                      } else {
+                          item.shrink == 0.0 || item.base < item.target
+                      };
+    }
+    let initial_free = available - gaps - items.iter()
+        .map(|item| if item.frozen { item.outer_target() } else { item.outer_base() })
+        .sum::<f32>();
+
+    while items.iter().any(|item| !item.frozen) {
+        let used: f32 = items.iter()
+            .map(|item| if item.frozen { item.outer_target() } else { item.outer_base() })
+            .sum();
+        let mut free = available - used - gaps;
+
+        // Flex factors that add up to less than one only take that fraction of the space.
+        let factors: f32 = items.iter().filter(|item| !item.frozen)
+            .map(|item| if growing { item.grow } else { item.shrink })
+            .sum();
+        if factors < 1.0 && (initial_free * factors).abs() < free.abs() {
+            free = initial_free * factors;
+        }
+
+        // Items shrink in proportion to their base size as well as their shrink factor.
+        let scaled: f32 = items.iter().filter(|item| !item.frozen)
+            .map(|item| item.shrink * item.base)
+            .sum();
+        let mut violations = Vec::with_capacity(items.len());
+        for item in items.iter_mut() {
+            if item.frozen {
+                violations.push(0.0);
+                continue;
+            }
+            let share = if growing {
+                free * item.grow / factors
+            } else if scaled > 0.0 {
+                free * item.shrink * item.base / scaled
+            } else {
+                0.0
+            };
+            let clamped = item.clamp(item.base + share);
+            violations.push(clamped - (item.base + share));
+            item.target = clamped;
+        }
+
+        // Freeze the items that were clamped, or all of them if none was.
+        let total: f32 = violations.iter().sum();
+        for (item, violation) in items.iter_mut().zip(violations) {
+            if total == 0.0 || total > 0.0 && violation > 0.0 || total < 0.0 && violation < 0.0 {
+                item.frozen = true;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use layout::tests::{lay_out, children, assert_near};
+
+    #[test]
+    fn items_grow_by_their_flex_factors() {
+        lay_out("<div><p></p><p></p></div>",
+                "div { display: flex; width: 600px; } p { flex: 1 0 100px; } \
+                 p + p { flex-grow: 2; }", |root| {
+            assert_near(&children(root, |c| c.width), &[233.33, 366.67]);
+            assert_near(&children(root, |c| c.x), &[0.0, 233.33]);
+        });
+    }
+
+    #[test]
+    fn items_shrink_by_their_factors_and_base_sizes() {
+        lay_out("<div><p></p><p></p></div>",
+                "div { display: flex; width: 300px; } p { flex: 0 1 200px; } \
+                 p + p { flex-basis: 400px; }", |root| {
+            assert_near(&children(root, |c| c.width), &[100.0, 200.0]);
+        });
+    }
+
+    #[test]
+    fn items_are_frozen_at_their_limits() {
+        lay_out("<div><p id=\"a\"></p><p></p></div>",
+                "div { display: flex; width: 600px; } p { flex: 1 0 0px; } \
+                 #a { max-width: 100px; }", |root| {
+            assert_near(&children(root, |c| c.width), &[100.0, 500.0]);
+        });
+        lay_out("<div><p id=\"a\"></p><p></p><p></p></div>",
+                "div { display: flex; width: 600px; } p { flex: 1 0 0px; } \
+                 #a { min-width: 400px; }", |root| {
+            assert_near(&children(root, |c| c.width), &[400.0, 100.0, 100.0]);
+        });
+    }
+
+    #[test]
+    fn bases_follow_box_sizing() {
+        lay_out("<div><p></p></div>",
+                "div { display: flex; width: 600px; } \
+                 p { flex: 0 0 100px; padding: 0px 10px; box-sizing: border-box; }", |root| {
+            assert_near(&children(root, |c| c.width), &[80.0]);
+            assert_near(&children(root, |c| c.x), &[10.0]);
+        });
+    }
+
+    #[test]
+    fn items_wrap_into_lines_with_gaps() {
+        lay_out("<div><p></p><p></p><p></p></div>",
+                "div { display: flex; flex-wrap: wrap; width: 300px; gap: 5px; } \
+                 p { flex: none; width: 120px; height: 10px; }", |root| {
+            assert_near(&children(root, |c| c.x), &[0.0, 125.0, 0.0]);
+            assert_near(&children(root, |c| c.y), &[0.0, 0.0, 15.0]);
+        });
+    }
+
+    #[test]
+    fn columns_flex_into_a_definite_height() {
+        lay_out("<div><p></p><p></p></div>",
+                "div { display: flex; flex-direction: column; width: 100px; height: 300px; } \
+                 p { flex: 1; } p + p { flex: 2; }", |root| {
+            assert_near(&children(root, |c| c.height), &[100.0, 200.0]);
+            assert_near(&children(root, |c| c.y), &[0.0, 100.0]);
+            assert_near(&children(root, |c| c.width), &[100.0, 100.0]);
+        });
+    }
+}
@@ -0,0 +1,214 @@
+// Floats: boxes taken out of the flow of a block container and pushed to its left or right edge,
+// with the boxes after them flowing around them.
+//
+// http://www.w3.org/TR/CSS2/visuren.html#floats
+//
+// The floats of a block formatting context are shared by all the blocks in its flow, however
+// deeply nested. Those blocks overlap floats as they should, but their content flows around them:
+// with no inline layout to shorten line boxes, the boxes that hold text (text nodes and generated
+// content) are narrowed to fit beside the floats instead. So are blocks that establish a new
+// block formatting context.
+
+use super::{LayoutBox, BoxType, Rect, keyword};
+use dom::NodeType;
+use std::f32;
+
+#[derive(Clone, Copy, PartialEq)]
+pub(super) enum Side {
+    Left,
+    Right,
+}
+
+/// The floats placed so far in a block container, by their border boxes (which include the
+/// margins that borders stand in for).
+pub(super) struct Floats {
+    boxes: Vec<(Side, Rect)>,
+    top: f32, // top of the last float placed, which later floats can't go above
+}
+
+impl Floats {
+    pub(super) fn new() -> Floats {
+        Floats { boxes: Vec::new(), top: f32::NEG_INFINITY }
+    }
+
+    /// The position `y` moves down to to clear the floats on the sides that `clear` names.
+    pub(super) fn clear(&self, clear: &str, y: f32) -> f32 {
+        self.boxes.iter()
+            .filter(|&&(side, _)| match clear {
+                "left" => side == Side::Left,
+                "right" => side == Side::Right,
+                "both" => true,
+                _ => false
+            })
+            .map(|&(_, rect)| rect.y + rect.height)
+            .fold(y, f32::max)
+    }
+
+    /// Find the highest place at or below `y` where a box `width` wide and `height` tall fits
+    /// beside the floats within `container`, as its top and the left and right edges of the space
+    /// there. A box too wide to fit anywhere goes below all the floats.
+    pub(super) fn fit(&self, container: Rect, mut y: f32, width: f32, height: f32)
+                      -> (f32, f32, f32) {
+        loop {
+            let (left, right) = self.band(container, y, height);
+            let next = self.boxes.iter()
+                .map(|&(_, rect)| rect.y + rect.height)
+                .filter(|&bottom| bottom > y)
+                .fold(f32::INFINITY, f32::min);
+            if right - left >= width || next == f32::INFINITY {
+                return (y, left, right);
+            }
+            // Try again below the first float in the way to end.
+            y = next;
+        }
+    }
+
+    /// Place a float `width` wide and `height` tall as high as it can go at or below `y`, and as
+    /// far to its side as it can go there. Returns the position of its top left corner.
+    ///
+    /// http://www.w3.org/TR/CSS2/visuren.html#float-rules
+    pub(super) fn place(&mut self, side: Side, container: Rect, y: f32, width: f32, height: f32)
+                        -> (f32, f32) {
+        let (y, left, right) = self.fit(container, y.max(self.top), width, height);
+        let x = match side {
+            Side::Left => left,
+            Side::Right => right - width,
+        };
+        self.boxes.push((side, Rect { x, y, width, height }));
+        self.top = y;
+        (x, y)
+    }
+
+    // The left and right edges of the space within `container` between the floats beside a box
+    // `height` tall at `y`.
+    fn band(&self, container: Rect, y: f32, height: f32) -> (f32, f32) {
+        let (mut left, mut right) = (container.x, container.x + container.width);
+        for &(side, rect) in &self.boxes {
+            let beside = rect.y + rect.height > y && (rect.y < y + height || rect.y <= y);
+            match side {
+                Side::Left if beside => left = left.max(rect.x + rect.width),
+                Side::Right if beside => right = right.min(rect.x),
+                _ => {}
+            }
+        }
+        (left, right)
+    }
+}
+
+impl<'a> LayoutBox<'a> {
+    /// The side the box floats to, if it floats. Absolutely positioned boxes don't.
+    pub(super) fn float_side(&self) -> Option<Side> {
+        if self.is_out_of_flow() {
+            return None;
+        }
+        match keyword(&self.style_node()?.style().reset.float, "none") {
+            "left" => Some(Side::Left),
+            "right" => Some(Side::Right),
+            _ => None
+        }
+    }
+
+    /// Whether the box floats.
+    pub fn is_float(&self) -> bool {
+        self.float_side().is_some()
+    }
+
+    /// The value of the box's `clear` property.
+    pub(super) fn clear(&self) -> &'a str {
+        match self.style_node() {
+            Some(node) => keyword(&node.style().reset.clear, "none"),
+            None => "none"
+        }
+    }
+
+    /// Whether the box holds text, which would be laid out in line boxes beside any floats: a
+    /// text node, or the content of a pseudo-element.
+    pub(super) fn holds_text(&self) -> bool {
+        match self.box_type {
+            BoxType::Generated(..) => true,
+            BoxType::Vertical(node) => matches!(node.node.node_type, NodeType::Text(_)),
+            _ => false
+        }
+    }
+
+    /// Whether a box in the flow of a block container establishes a new block formatting context
+    /// for its children, which keeps it from overlapping floats outside and makes it contain the
+    /// floats inside.
+    ///
+    /// http://www.w3.org/TR/CSS2/visuren.html#block-formatting
+    pub(super) fn establishes_bfc(&self) -> bool {
+        match self.box_type {
+            BoxType::Vertical(node) | BoxType::Generated(node, _) => {
+                let style = &node.style().reset;
+                keyword(&style.overflow, "visible") != "visible" ||
+                    keyword(&style.display, "inline") == "flow-root"
+            }
+            BoxType::AnonymousBlock => false,
+            _ => true
+        }
+    }
+
+    /// Grow a box whose children are laid out as blocks to contain the floats among them.
+    ///
+    /// http://www.w3.org/TR/CSS2/visudet.html#root-height
+    pub(super) fn contain_floats(&mut self) {
+        if !self.is_block_container() {
+            return;
+        }
+        let bottom = self.children.iter()
+            .map(LayoutBox::float_bottom)
+            .fold(f32::NEG_INFINITY, f32::max);
+        let content = &mut self.dimensions.content;
+        content.height = content.height.max(bottom - content.y);
+    }
+
+    // The bottom of the box if it floats, or else of the lowest float in its block formatting
+    // context among its descendants.
+    fn float_bottom(&self) -> f32 {
+        if self.float_side().is_some() {
+            let border_box = self.dimensions.border_box();
+            border_box.y + border_box.height
+        } else if self.establishes_bfc() {
+            f32::NEG_INFINITY
+        } else {
+            self.children.iter().map(LayoutBox::float_bottom).fold(f32::NEG_INFINITY, f32::max)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use layout::tests::{lay_out, children, assert_near};
+
+    const FLOATS: &str = "* { display: block; } \
+                         .f { float: left; width: 100px; height: 50px; }";
+
+    #[test]
+    fn nested_blocks_clear_outer_floats() {
+        lay_out("<div><p class=\"f\"></p><div><p id=\"c\"></p></div></div>",
+                &format!("{} #c {{ clear: left; height: 10px; }}", FLOATS), |root| {
+            assert_near(&children(&root.children[1], |c| c.y), &[50.0]);
+        });
+    }
+
+    #[test]
+    fn nested_floats_push_later_floats() {
+        lay_out("<div><div><p class=\"f\"></p></div><p class=\"f\"></p></div>", FLOATS, |root| {
+            assert_near(&children(root, |c| c.x), &[0.0, 100.0]);
+            assert_near(&children(root, |c| c.y), &[0.0, 0.0]);
+        });
+    }
+
+    #[test]
+    fn text_is_narrowed_beside_floats() {
+        lay_out("<div><p class=\"f\"></p><div>text</div></div>", FLOATS, |root| {
+            let block = &root.children[1];
+            assert_near(&children(block, |c| c.x), &[100.0]);
+            assert_near(&children(block, |c| c.width), &[700.0]);
+        });
+        lay_out("<div><p class=\"f\"></p><div id=\"g\"></div></div>",
+                &format!("{} #g::before {{ content: \"text\"; }}", FLOATS), |root| {
+            assert_near(&children(&root.children[1], |c| c.x), &[100.0]);
+        });
+    }
+}
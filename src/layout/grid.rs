@@ -0,0 +1,682 @@
+// Grid layout: a formatting context that places its children in the cells of a grid of rows
+// and columns, sized by the container's track lists and by the items in them.
+//
+// https://www.w3.org/TR/css-grid-1/
+//
+// There's no inline layout to measure content with, so items contribute nothing to the width of
+// a column unless they set one; columns sized to their content share the space left over.
+
+use super::{LayoutBox, keyword, gap, definite, distribute, spacing};
+use css::{Value, Track, Repetitions};
+use css::Value::{Keyword, Length, Number, Span};
+use css::Unit::Fr;
+use properties::ResetStyle;
+use std::collections::HashMap;
+use std::f32;
+
+// The explicit tracks of one axis of a grid, with the names of the lines between them.
+#[derive(Default)]
+struct Axis {
+    tracks: Vec<(Value, Value)>,        // the min and max sizing functions of each track
+    names: HashMap<String, Vec<usize>>, // line indices by name, counting from 0
+}
+
+// A track being sized.
+struct TrackSize {
+    min: Value,
+    max: Value,
+    base: f32,
+    limit: f32, // growth limit, which may be infinite
+}
+
+// One side of an item's placement along an axis, from `grid-row-start` and friends.
+#[derive(Clone, Copy)]
+enum Line {
+    Auto,
+    At(i32), // a line index, counting from 0
+    Span(usize),
+}
+
+// The tracks an item occupies along an axis, or how many it spans until it's auto-placed.
+#[derive(Clone, Copy)]
+enum Placement {
+    Definite(usize, usize), // start and end line indices
+    Auto(usize),
+}
+
+impl Placement {
+    fn span(&self) -> usize {
+        match *self {
+            Placement::Definite(start, end) => end - start,
+            Placement::Auto(span) => span,
+        }
+    }
+}
+
+impl<'a> LayoutBox<'a> {
+    /// Lay out the box's children as grid items. `height` is the height of the content area if
+    /// it's known up front.
+    ///
+    /// https://www.w3.org/TR/css-grid-1/#layout-algorithm
+    ///
+    /// Sets `self.dimensions.height` to the height of the rows.
+    pub(super) fn layout_grid_items(&mut self, height: Option<f32>) {
+        let style = &self.get_style_node().style().reset;
+        let content = self.dimensions.content;
+        let row_gap = gap(&style.row_gap, height.unwrap_or(0.0));
+        let column_gap = gap(&style.column_gap, content.width);
+
+        // The explicit grid, from the track lists and the named areas.
+        let mut columns = explicit_axis(&style.grid_template_columns, Some(content.width),
+                                        column_gap);
+        let mut rows = explicit_axis(&style.grid_template_rows, height, row_gap);
+        let (explicit_rows, explicit_columns) = match style.grid_template_areas {
+            Some(Value::Areas(ref areas)) => {
+                name_areas(areas, &mut rows, &mut columns);
+                (rows.tracks.len().max(areas.len()), columns.tracks.len().max(areas[0].len()))
+            }
+            _ => (rows.tracks.len(), columns.tracks.len())
+        };
+
+        // Place the items, first those whose position is known and then the rest.
+        self.sort_children_by_order();
+        // Anonymous items have the initial value of every property.
+        let initial = ResetStyle::default();
+        let item_style = |child: &LayoutBox<'a>| match child.style_node() {
+            Some(node) => &node.style().reset,
+            None => &initial
+        };
+        let mut placements: Vec<(Placement, Placement)> = self.children.iter().map(|child| {
+            let style = item_style(child);
+            (placement(resolve_line(&style.grid_row_start, &rows, explicit_rows, "start"),
+                       resolve_line(&style.grid_row_end, &rows, explicit_rows, "end")),
+             placement(resolve_line(&style.grid_column_start, &columns, explicit_columns,
+                                    "start"),
+                       resolve_line(&style.grid_column_end, &columns, explicit_columns, "end")))
+        }).collect();
+        let flow = keyword(&style.grid_auto_flow, "row");
+        let column_flow = flow.starts_with("column");
+        let dense = flow.contains("dense");
+        if column_flow {
+            for placement in &mut placements {
+                *placement = (placement.1, placement.0);
+            }
+        }
+        let minor_count = if column_flow { explicit_rows } else { explicit_columns };
+        let areas = auto_place(&placements, minor_count, dense);
+        let areas: Vec<((usize, usize), (usize, usize))> = if column_flow {
+            areas.into_iter().map(|(major, minor)| (minor, major)).collect()
+        } else {
+            areas
+        };
+        let row_count = areas.iter().map(|area| (area.0).1).fold(explicit_rows, usize::max);
+        let column_count = areas.iter().map(|area| (area.1).1).fold(explicit_columns, usize::max);
+
+        // Size the columns, then lay the items out across them to find the heights of the rows.
+        let mut column_sizes = track_sizes(&columns, column_count, &style.grid_auto_columns,
+                                           Some(content.width));
+        let column_contributions: Vec<(usize, usize, f32)> = self.children.iter().zip(&areas)
+            .filter_map(|(child, &(_, (start, end)))| {
+                child.fixed_outer_width(content.width).map(|width| (start, end, width))
+            })
+            .collect();
+        size_tracks(&mut column_sizes, Some(content.width), column_gap, &column_contributions,
+                    keyword(&style.justify_content, "normal"));
+
+        let mut row_sizes = track_sizes(&rows, row_count, &style.grid_auto_rows, height);
+        let row_contributions: Vec<(usize, usize, f32)> = self.children.iter_mut().zip(&areas)
+            .map(|(child, &((start, end), columns))| {
+                let width = area_size(&column_sizes, columns, column_gap);
+                child.layout_sized(0.0, 0.0, width, None, content.width, None);
+                (start, end, child.dimensions.border_box().height)
+            })
+            .collect();
+        size_tracks(&mut row_sizes, height, row_gap, &row_contributions,
+                    keyword(&style.align_content, "normal"));
+
+        // Align the grid within the container, and the items within their areas.
+        let column_starts = track_starts(&column_sizes, Some(content.width), column_gap,
+                                         keyword(&style.justify_content, "normal"));
+        let row_starts = track_starts(&row_sizes, height, row_gap,
+                                      keyword(&style.align_content, "normal"));
+        let justify_items = keyword(&style.justify_items, "normal");
+        let align_items = keyword(&style.align_items, "normal");
+        for (child, &(rows, columns)) in self.children.iter_mut().zip(&areas) {
+            let item_style = item_style(child);
+            let area_width = area_size(&column_sizes, columns, column_gap);
+            let area_height = area_size(&row_sizes, rows, row_gap);
+            let x = content.x + column_starts[columns.0];
+            let y = content.y + row_starts[rows.0];
+
+            let justify = self_alignment(&item_style.justify_self, justify_items);
+            let align = self_alignment(&item_style.align_self, align_items);
+            // Without a width of its own an item fills its area, whatever its alignment.
+            let width = child.fixed_outer_width(content.width).unwrap_or(area_width);
+            let stretch = align == "stretch" && definite(&item_style.height, None).is_none();
+            child.layout_sized(x, y, width, if stretch { Some(area_height) } else { None },
+                               content.width, Some(area_height));
+            let height = child.dimensions.border_box().height;
+            child.translate(offset(justify, area_width - width),
+                            offset(align, area_height - height));
+        }
+
+        self.dimensions.content.height = height.unwrap_or(
+            row_sizes.iter().map(|track| track.base).sum::<f32>() +
+                spacing(row_gap, row_sizes.len()));
+    }
+
+}
+
+// Build the explicit tracks of an axis from a track list, repeating `repeat()`s as many times
+// as fit in `available` space if their count is `auto-fill` or `auto-fit`.
+fn explicit_axis(value: &Option<Value>, available: Option<f32>, gap: f32) -> Axis {
+    let mut axis: Axis = Default::default();
+    let list = match *value {
+        Some(Value::Tracks(ref list)) => list,
+        _ => return axis
+    };
+    for track in list {
+        add_track(&mut axis, track, list, available, gap);
+    }
+    axis
+}
+
+fn add_track(axis: &mut Axis, track: &Track, list: &[Track], available: Option<f32>, gap: f32) {
+    match *track {
+        Track::Names(ref names) => {
+            for name in names {
+                axis.names.entry(name.clone()).or_default().push(axis.tracks.len());
+            }
+        }
+        Track::Size(ref min, ref max) => axis.tracks.push((min.clone(), max.clone())),
+        Track::Repeat(count, ref tracks) => {
+            for _ in 0..repetitions(count, tracks, list, available, gap) {
+                for track in tracks {
+                    add_track(axis, track, list, available, gap);
+                }
+            }
+        }
+    }
+}
+
+// How many times a `repeat()` repeats. An automatic count fills the space left by the other
+// tracks, or is 1 if the space isn't known.
+//
+// `auto-fit` is treated as `auto-fill`: empty repeated tracks aren't collapsed.
+fn repetitions(count: Repetitions, tracks: &[Track], list: &[Track], available: Option<f32>,
+               gap: f32) -> usize {
+    let available = match (count, available) {
+        (Repetitions::Count(n), _) => return n as usize,
+        (_, None) => return 1,
+        (_, Some(available)) => available
+    };
+    // The fixed sizes of a list of tracks, and how many tracks there are.
+    let fixed = |tracks: &[Track]| tracks.iter().fold((0.0, 0), |(size, count), track| {
+        match *track {
+            Track::Size(ref min, ref max) => {
+                let size_of = definite(&Some(max.clone()), Some(available))
+                    .or(definite(&Some(min.clone()), Some(available)))
+                    .unwrap_or(0.0);
+                (size + size_of, count + 1)
+            }
+            _ => (size, count)
+        }
+    });
+    let (repeated, repeated_count) = fixed(tracks);
+    let (other, other_count) = fixed(list);
+    let per_repetition = repeated + gap * repeated_count as f32;
+    if per_repetition <= 0.0 {
+        return 1;
+    }
+    let space = available - other - gap * other_count as f32 + gap;
+    ((space / per_repetition).floor() as usize).max(1)
+}
+
+// Add the implicit line names of `grid-template-areas`: each area named `a` runs from lines
+// `a-start` to `a-end` on both axes.
+fn name_areas(areas: &[Vec<String>], rows: &mut Axis, columns: &mut Axis) {
+    let mut bounds: HashMap<&str, (usize, usize, usize, usize)> = HashMap::new();
+    for (row, cells) in areas.iter().enumerate() {
+        for (column, name) in cells.iter().enumerate() {
+            if name.chars().all(|c| c == '.') {
+                continue;
+            }
+            let area = bounds.entry(name).or_insert((row, row, column, column));
+            area.0 = area.0.min(row);
+            area.1 = area.1.max(row);
+            area.2 = area.2.min(column);
+            area.3 = area.3.max(column);
+        }
+    }
+    for (name, (top, bottom, left, right)) in bounds {
+        let add = |axis: &mut Axis, suffix: &str, line: usize| {
+            axis.names.entry(format!("{}-{}", name, suffix)).or_default().push(line);
+        };
+        add(rows, "start", top);
+        add(rows, "end", bottom + 1);
+        add(columns, "start", left);
+        add(columns, "end", right + 1);
+    }
+}
+
+// Resolve one side of an item's placement. Negative line numbers count back from the end of the
+// explicit grid, and a name finds the first line called `<name>-start` or `<name>-end` (for
+// areas), or else just `<name>`. Unknown names, and lines before the start of the grid, fall
+// back to automatic placement and the first line.
+fn resolve_line(value: &Option<Value>, axis: &Axis, explicit: usize, side: &str) -> Line {
+    match *value {
+        Some(Number(n)) if n > 0.0 => Line::At(n as i32 - 1),
+        Some(Number(n)) => Line::At((explicit as i32 + 1 + n as i32).max(0)),
+        Some(Span(n)) => Line::Span(n as usize),
+        Some(Keyword(ref name)) if name != "auto" => {
+            axis.names.get(&format!("{}-{}", name, side))
+                .or(axis.names.get(name))
+                .map_or(Line::Auto, |lines| Line::At(lines[0] as i32))
+        }
+        _ => Line::Auto
+    }
+}
+
+// Combine the start and end lines of an item along an axis.
+//
+// https://www.w3.org/TR/css-grid-1/#line-placement
+fn placement(start: Line, end: Line) -> Placement {
+    match (start, end) {
+        (Line::At(start), Line::At(end)) if start == end => {
+            Placement::Definite(start as usize, start as usize + 1)
+        }
+        (Line::At(start), Line::At(end)) => {
+            Placement::Definite(start.min(end) as usize, start.max(end) as usize)
+        }
+        (Line::At(start), Line::Span(span)) => Placement::Definite(start as usize,
+                                                                   start as usize + span),
+        (Line::At(start), Line::Auto) => Placement::Definite(start as usize, start as usize + 1),
+        (Line::Span(span), Line::At(end)) => {
+            Placement::Definite((end - span as i32).max(0) as usize, end.max(1) as usize)
+        }
+        (Line::Auto, Line::At(end)) => {
+            Placement::Definite((end - 1).max(0) as usize, end.max(1) as usize)
+        }
+        (Line::Span(span), _) | (Line::Auto, Line::Span(span)) => Placement::Auto(span),
+        (Line::Auto, Line::Auto) => Placement::Auto(1),
+    }
+}
+
+// Place every item in the grid, given as (major, minor) placements where the major axis is the
+// one `grid-auto-flow` adds tracks to. Returns the (major, minor) line ranges of the items.
+//
+// https://www.w3.org/TR/css-grid-1/#auto-placement-algo
+fn auto_place(placements: &[(Placement, Placement)], explicit_minor: usize, dense: bool)
+              -> Vec<((usize, usize), (usize, usize))> {
+    let minor_count = placements.iter().map(|&(_, minor)| match minor {
+        Placement::Definite(_, end) => end,
+        Placement::Auto(span) => span,
+    }).fold(explicit_minor.max(1), usize::max);
+
+    let mut grid = Occupancy { cells: Vec::new(), minor_count };
+    let mut areas = vec![((0, 0), (0, 0)); placements.len()];
+    let mut placed = vec![false; placements.len()];
+
+    // Items with a definite position along both axes.
+    for (i, &placement) in placements.iter().enumerate() {
+        if let (Placement::Definite(a, b), Placement::Definite(c, d)) = placement {
+            grid.occupy((a, b), (c, d));
+            areas[i] = ((a, b), (c, d));
+            placed[i] = true;
+        }
+    }
+
+    // Items locked to a major track, in the first minor position they fit.
+    let mut cursors: HashMap<usize, usize> = HashMap::new();
+    for (i, &placement) in placements.iter().enumerate() {
+        if let (Placement::Definite(a, b), Placement::Auto(span)) = placement {
+            let cursor = cursors.entry(a).or_insert(0);
+            let mut minor = if dense { 0 } else { *cursor };
+            while minor + span <= minor_count && !grid.fits((a, b), (minor, minor + span)) {
+                minor += 1;
+            }
+            grid.occupy((a, b), (minor, minor + span));
+            areas[i] = ((a, b), (minor, minor + span));
+            placed[i] = true;
+            *cursor = minor + span;
+        }
+    }
+
+    // Everything else, in order from a cursor that only moves forward unless packing densely.
+    let (mut major, mut minor) = (0, 0);
+    for (i, &(major_placement, minor_placement)) in placements.iter().enumerate() {
+        if placed[i] {
+            continue;
+        }
+        if dense {
+            major = 0;
+            minor = 0;
+        }
+        let major_span = major_placement.span();
+        match minor_placement {
+            Placement::Definite(start, end) => {
+                if start < minor {
+                    major += 1;
+                }
+                minor = start;
+                while !grid.fits((major, major + major_span), (start, end)) {
+                    major += 1;
+                }
+            }
+            Placement::Auto(span) => loop {
+                if minor + span > minor_count {
+                    major += 1;
+                    minor = 0;
+                } else if grid.fits((major, major + major_span), (minor, minor + span)) {
+                    break;
+                } else {
+                    minor += 1;
+                }
+            }
+        }
+        let minor_end = minor + minor_placement.span();
+        grid.occupy((major, major + major_span), (minor, minor_end));
+        areas[i] = ((major, major + major_span), (minor, minor_end));
+        minor = minor_end;
+    }
+    areas
+}
+
+// Which cells of the grid are taken, by major then minor track.
+struct Occupancy {
+    cells: Vec<Vec<bool>>,
+    minor_count: usize,
+}
+
+impl Occupancy {
+    fn fits(&self, major: (usize, usize), minor: (usize, usize)) -> bool {
+        (major.0..major.1).all(|i| match self.cells.get(i) {
+            Some(cells) => (minor.0..minor.1).all(|j| !cells.get(j).cloned().unwrap_or(false)),
+            None => true
+        })
+    }
+
+    fn occupy(&mut self, major: (usize, usize), minor: (usize, usize)) {
+        while self.cells.len() < major.1 {
+            self.cells.push(vec![false; self.minor_count]);
+        }
+        for cells in &mut self.cells[major.0..major.1] {
+            if cells.len() < minor.1 {
+                cells.resize(minor.1, false);
+            }
+            for cell in &mut cells[minor.0..minor.1] {
+                *cell = true;
+            }
+        }
+    }
+}
+
+// The sizing functions of every track along an axis. Implicit tracks cycle through the
+// `grid-auto-rows` or `grid-auto-columns` list.
+fn track_sizes(axis: &Axis, count: usize, auto: &Option<Value>, available: Option<f32>)
+               -> Vec<TrackSize> {
+    let implicit: Vec<(Value, Value)> = match *auto {
+        Some(Value::Tracks(ref list)) => list.iter().filter_map(|track| match *track {
+            Track::Size(ref min, ref max) => Some((min.clone(), max.clone())),
+            _ => None
+        }).collect(),
+        _ => Vec::new()
+    };
+    (0..count).map(|i| {
+        let (min, max) = match axis.tracks.get(i) {
+            Some(track) => track.clone(),
+            None if implicit.is_empty() => (Keyword(String::from("auto")),
+                                            Keyword(String::from("auto"))),
+            None => implicit[(i - axis.tracks.len()) % implicit.len()].clone()
+        };
+        // Fixed sizes start at their size; content-sized ones start at nothing.
+        let base = definite(&Some(min.clone()), available).unwrap_or(0.0);
+        let limit = match definite(&Some(max.clone()), available) {
+            Some(limit) => limit.max(base),
+            None if is_flexible(&max) => base,
+            None => f32::INFINITY
+        };
+        TrackSize { min, max, base, limit }
+    }).collect()
+}
+
+fn is_flexible(value: &Value) -> bool {
+    matches!(*value, Length(_, Fr))
+}
+
+// Whether a sizing function depends on the size of the items in the track.
+fn is_intrinsic(value: &Value, available: Option<f32>) -> bool {
+    definite(&Some(value.clone()), available).is_none()
+}
+
+// Size the tracks of an axis to fit the items in them, given as (start, end, outer size), and
+// share out the `available` space if it's known.
+//
+// https://www.w3.org/TR/css-grid-1/#algo-track-sizing
+fn size_tracks(tracks: &mut [TrackSize], available: Option<f32>, gap: f32,
+               contributions: &[(usize, usize, f32)], content_distribution: &str) {
+    // Grow content-sized tracks to fit their items, narrower spans first. Items that span a
+    // flexible track only grow the flexible ones.
+    let mut contributions = contributions.to_vec();
+    contributions.sort_by_key(|&(start, end, _)| end - start);
+    for &(start, end, size) in &contributions {
+        let spanned = &mut tracks[start..end];
+        let flexible = spanned.iter().any(|track| is_flexible(&track.max));
+        let current = spanned.iter().map(|track| track.base).sum::<f32>() +
+                      spacing(gap, end - start);
+        let growable: Vec<usize> = (0..spanned.len()).filter(|&i| {
+            is_intrinsic(&spanned[i].min, available) && is_flexible(&spanned[i].max) == flexible
+        }).collect();
+        if size > current && !growable.is_empty() {
+            let share = (size - current) / growable.len() as f32;
+            for &i in &growable {
+                spanned[i].base += share;
+            }
+        }
+        // A content-sized maximum is the largest size the items need.
+        if end - start == 1 && !flexible && is_intrinsic(&spanned[0].max, available) {
+            let track = &mut spanned[0];
+            track.limit = if track.limit.is_finite() { track.limit.max(size) } else { size };
+        }
+    }
+    for track in tracks.iter_mut() {
+        if !track.limit.is_finite() || track.limit < track.base {
+            track.limit = track.base;
+        }
+    }
+
+    let available = match available {
+        Some(available) => available,
+        None => {
+            // Without a size to fill, every track grows to its limit, and a fraction is as big
+            // as the largest flexible track needs.
+            for track in tracks.iter_mut() {
+                track.base = track.limit;
+            }
+            let fraction = tracks.iter().filter_map(|track| match track.max {
+                Length(flex, Fr) if flex > 0.0 => Some(track.base / flex.max(1.0)),
+                _ => None
+            }).fold(0.0, f32::max);
+            grow_flexible_tracks(tracks, fraction);
+            return;
+        }
+    };
+    let gaps = spacing(gap, tracks.len());
+
+    // Grow every track towards its limit.
+    let used: f32 = tracks.iter().map(|track| track.base).sum();
+    let mut free = available - used - gaps;
+    while free > 0.01 {
+        let growing: Vec<usize> = (0..tracks.len())
+            .filter(|&i| tracks[i].limit > tracks[i].base)
+            .collect();
+        if growing.is_empty() {
+            break;
+        }
+        let share = free / growing.len() as f32;
+        for &i in &growing {
+            let grown = (tracks[i].base + share).min(tracks[i].limit);
+            free -= grown - tracks[i].base;
+            tracks[i].base = grown;
+        }
+    }
+
+    // Share what's left between the flexible tracks, in proportion to their flex factors. A
+    // track already bigger than its share keeps its size and leaves the rest to the others.
+    let mut inflexible = vec![false; tracks.len()];
+    loop {
+        let leftover = available - gaps - tracks.iter().zip(&inflexible)
+            .filter(|&(track, &inflexible)| inflexible || !is_flexible(&track.max))
+            .map(|(track, _)| track.base)
+            .sum::<f32>();
+        let factors: f32 = tracks.iter().zip(&inflexible)
+            .filter_map(|(track, &inflexible)| match track.max {
+                Length(flex, Fr) if !inflexible => Some(flex),
+                _ => None
+            })
+            .sum();
+        if factors == 0.0 {
+            break;
+        }
+        let fraction = leftover.max(0.0) / factors.max(1.0);
+        let mut changed = false;
+        for (track, inflexible) in tracks.iter().zip(inflexible.iter_mut()) {
+            if let Length(flex, Fr) = track.max {
+                if !*inflexible && track.base > flex * fraction {
+                    *inflexible = true;
+                    changed = true;
+                }
+            }
+        }
+        if !changed {
+            grow_flexible_tracks(tracks, fraction);
+            break;
+        }
+    }
+
+    // Stretch the `auto` tracks over any space still free.
+    if content_distribution == "normal" || content_distribution == "stretch" {
+        let used: f32 = tracks.iter().map(|track| track.base).sum();
+        let free = available - used - gaps;
+        let autos: Vec<usize> = (0..tracks.len())
+            .filter(|&i| tracks[i].max == Keyword(String::from("auto")))
+            .collect();
+        if free > 0.0 && !autos.is_empty() {
+            for &i in &autos {
+                tracks[i].base += free / autos.len() as f32;
+            }
+        }
+    }
+}
+
+fn grow_flexible_tracks(tracks: &mut [TrackSize], fraction: f32) {
+    for track in tracks {
+        if let Length(flex, Fr) = track.max {
+            track.base = track.base.max(flex * fraction);
+        }
+    }
+}
+
+// The offset of each track from the start of the content area, after `justify-content` or
+// `align-content` distributes any free space.
+fn track_starts(tracks: &[TrackSize], available: Option<f32>, gap: f32, value: &str) -> Vec<f32> {
+    let used = tracks.iter().map(|track| track.base).sum::<f32>() + spacing(gap, tracks.len());
+    let (mut position, extra) = match available {
+        Some(available) => distribute(value, available - used, tracks.len()),
+        None => (0.0, 0.0)
+    };
+    tracks.iter().map(|track| {
+        let start = position;
+        position += track.base + gap + extra;
+        start
+    }).collect()
+}
+
+// The size of the grid area spanning `tracks`, including the gaps between them.
+fn area_size(sizes: &[TrackSize], tracks: (usize, usize), gap: f32) -> f32 {
+    sizes[tracks.0..tracks.1].iter().map(|track| track.base).sum::<f32>() +
+        spacing(gap, tracks.1 - tracks.0)
+}
+
+// `justify-self` or `align-self`, with `auto` resolved from the container and `normal` as
+// `stretch`.
+fn self_alignment<'v>(value: &'v Option<Value>, items: &'v str) -> &'v str {
+    match keyword(value, "auto") {
+        "auto" | "normal" if items == "normal" => "stretch",
+        "auto" => items,
+        "normal" => "stretch",
+        align => align
+    }
+}
+
+// The offset of an item in its area for a self-alignment value, given the space it leaves.
+fn offset(alignment: &str, free: f32) -> f32 {
+    match alignment {
+        "end" | "flex-end" | "self-end" | "right" => free,
+        "center" => free / 2.0,
+        _ => 0.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use layout::tests::{lay_out, children, assert_near};
+
+    #[test]
+    fn fixed_and_flexible_columns() {
+        lay_out("<div><p></p><p></p><p></p></div>",
+                "div { display: grid; width: 600px; grid-template-columns: 100px 1fr 2fr; }",
+                |root| {
+            assert_near(&children(root, |c| c.width), &[100.0, 166.67, 333.33]);
+            assert_near(&children(root, |c| c.x), &[0.0, 100.0, 266.67]);
+        });
+    }
+
+    #[test]
+    fn minmax_grows_to_its_limit_before_fractions() {
+        lay_out("<div><p></p><p></p></div>",
+                "div { display: grid; width: 600px; \
+                       grid-template-columns: minmax(100px, 200px) 1fr; }", |root| {
+            assert_near(&children(root, |c| c.width), &[200.0, 400.0]);
+        });
+    }
+
+    #[test]
+    fn auto_columns_fit_items_with_a_width() {
+        lay_out("<div><p id=\"a\"></p><p></p><p></p></div>",
+                "div { display: grid; width: 600px; grid-template-columns: auto auto 1fr; } \
+                 #a { width: 150px; }", |root| {
+            assert_near(&children(root, |c| c.width), &[150.0, 0.0, 450.0]);
+            assert_near(&children(root, |c| c.x), &[0.0, 150.0, 150.0]);
+        });
+        // Without a flexible track, the auto tracks share the space left over.
+        lay_out("<div><p id=\"a\"></p><p></p></div>",
+                "div { display: grid; width: 600px; grid-template-columns: auto auto; } \
+                 #a { width: 100px; }", |root| {
+            assert_near(&children(root, |c| c.x), &[0.0, 350.0]);
+        });
+    }
+
+    #[test]
+    fn auto_fill_repeats_as_many_tracks_as_fit() {
+        lay_out("<div><p></p><p></p><p></p><p></p><p></p><p></p></div>",
+                "div { display: grid; width: 550px; gap: 10px; \
+                       grid-template-columns: repeat(auto-fill, 100px); } \
+                 p { height: 20px; }", |root| {
+            assert_near(&children(root, |c| c.x), &[0.0, 110.0, 220.0, 330.0, 440.0, 0.0]);
+            assert_near(&children(root, |c| c.y), &[0.0, 0.0, 0.0, 0.0, 0.0, 30.0]);
+        });
+    }
+
+    #[test]
+    fn rows_fit_their_tallest_items() {
+        lay_out("<div><p></p><p id=\"a\"></p><p></p><p></p></div>",
+                "div { display: grid; grid-template-columns: 1fr 1fr; \
+                       grid-template-rows: 50px auto; } \
+                 #a { height: 30px; } p + p + p + p { height: 70px; }", |root| {
+            assert_near(&children(root, |c| c.y), &[0.0, 0.0, 50.0, 50.0]);
+            assert_near(&children(root, |c| c.height), &[50.0, 30.0, 70.0, 70.0]);
+            assert_near(&[root.dimensions.content.height], &[120.0]);
+        });
+    }
+}
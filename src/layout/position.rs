@@ -0,0 +1,251 @@
+// Positioned layout: boxes moved from where normal flow puts them, and boxes taken out of the
+// flow and placed against their containing block or the viewport.
+//
+// https://www.w3.org/TR/css-position-3/
+//
+// There's no scrolling, so sticky boxes stick as they would with the document scrolled to the
+// top, and the viewport is the scrollport they stick in.
+
+use super::{LayoutBox, Rect, keyword, definite};
+use css::Value::Number;
+
+impl<'a> LayoutBox<'a> {
+    /// The value of the box's `position` property.
+    pub fn position(&self) -> &'a str {
+        match self.style_node() {
+            Some(node) => keyword(&node.style().reset.position, "static"),
+            None => "static"
+        }
+    }
+
+    /// Whether the box is positioned, which makes it the containing block of its absolutely
+    /// positioned descendants.
+    pub fn is_positioned(&self) -> bool {
+        self.position() != "static"
+    }
+
+    /// Whether the box is absolutely positioned, and so takes no part in the layout of its
+    /// siblings.
+    pub(super) fn is_out_of_flow(&self) -> bool {
+        matches!(self.position(), "absolute" | "fixed")
+    }
+
+    /// The box's `z-index`, or `None` if it's `auto`.
+    pub fn z_index(&self) -> Option<i32> {
+        match self.style_node()?.style().reset.z_index {
+            Some(Number(n)) => Some(n as i32),
+            _ => None
+        }
+    }
+
+    /// Move a relatively positioned box by its offsets, with percentages of `cb_width`.
+    ///
+    /// Percentages of `top` and `bottom` refer to the height of the containing block, which
+    /// isn't known yet, so they count as `auto`.
+    pub(super) fn offset_relative(&mut self, cb_width: f32) {
+        if self.position() != "relative" {
+            return;
+        }
+        let style = &self.get_style_node().style().reset;
+        let dx = match (definite(&style.left, Some(cb_width)),
+                        definite(&style.right, Some(cb_width))) {
+            (Some(left), _) => left,
+            (None, Some(right)) => -right,
+            (None, None) => 0.0
+        };
+        let dy = match (definite(&style.top, None), definite(&style.bottom, None)) {
+            (Some(top), _) => top,
+            (None, Some(bottom)) => -bottom,
+            (None, None) => 0.0
+        };
+        self.translate(dx, dy);
+    }
+
+    /// Lay out the absolutely positioned boxes among the box's descendants, and stick its sticky
+    /// ones. `containing_block` is the padding box of the nearest positioned ancestor, or the
+    /// initial containing block if there's none.
+    ///
+    /// Normal flow leaves out-of-flow boxes at their static position: the top left corner their
+    /// border box would have had in the flow.
+    pub(super) fn layout_positioned(&mut self, containing_block: Rect, viewport: Rect) {
+        let containing_block = if self.is_positioned() {
+            self.dimensions.padding_box()
+        } else {
+            containing_block
+        };
+        let content = self.dimensions.content;
+        for child in &mut self.children {
+            match child.position() {
+                "absolute" => child.layout_absolute(containing_block),
+                "fixed" => child.layout_absolute(viewport),
+                "sticky" => child.stick(viewport, content),
+                _ => {}
+            }
+            child.layout_positioned(containing_block, viewport);
+        }
+    }
+
+    // Size and place an absolutely positioned box against its containing block.
+    //
+    // http://www.w3.org/TR/CSS2/visudet.html#abs-non-replaced-width
+    //
+    // There's no content to shrink an `auto` size to fit, so it takes up the rest of the
+    // containing block.
+    fn layout_absolute(&mut self, cb: Rect) {
+        let (static_x, static_y) = (self.dimensions.content.x, self.dimensions.content.y);
+        self.dimensions = Default::default();
+        self.calculate_edges(cb.width);
+        let edges = self.dimensions.border_box();
+
+        let style = &self.get_style_node().style().reset;
+        let left = definite(&style.left, Some(cb.width));
+        let right = definite(&style.right, Some(cb.width));
+        let top = definite(&style.top, Some(cb.height));
+        let bottom = definite(&style.bottom, Some(cb.height));
+
+        // With `left` set, `right` is ignored if the width is set too.
+        let start = left.unwrap_or(static_x - cb.x);
+        let width = match self.specified_width(cb.width) {
+            Some(width) => width + edges.width,
+            None => {
+                let (min, max) = self.width_limits(cb.width);
+                let available = match (left, right) {
+                    (None, Some(right)) => cb.width - right,
+                    (_, right) => cb.width - start - right.unwrap_or(0.0)
+                };
+                (available - edges.width).min(max).max(min).max(0.0) + edges.width
+            }
+        };
+        let x = match (left, right) {
+            (None, Some(right)) => cb.x + cb.width - right - width,
+            _ => cb.x + start
+        };
+        let (min, max) = self.height_limits(cb.width, Some(cb.height));
+        let height = match (definite(&style.height, Some(cb.height)), top, bottom) {
+            (Some(height), _, _) => Some(height - self.vertical_box_sizing(cb.width)),
+            (None, Some(top), Some(bottom)) => Some(cb.height - top - bottom - edges.height),
+            _ => None
+        }.map(|height| height.min(max).max(min).max(0.0) + edges.height);
+
+        self.layout_within_edges(x, 0.0, width, height, cb.width, Some(cb.height));
+        let y = match (top, bottom) {
+            (Some(top), _) => cb.y + top,
+            (None, Some(bottom)) => cb.y + cb.height - bottom - self.dimensions.border_box().height,
+            (None, None) => static_y
+        };
+        self.translate(0.0, y);
+    }
+
+    // Move a sticky box as far as it needs to go to keep its offsets from the edges of
+    // `scrollport`, without leaving `cb`, the content box of its parent.
+    fn stick(&mut self, scrollport: Rect, cb: Rect) {
+        let style = &self.get_style_node().style().reset;
+        let (top, bottom) = (definite(&style.top, Some(scrollport.height)),
+                             definite(&style.bottom, Some(scrollport.height)));
+        let (left, right) = (definite(&style.left, Some(scrollport.width)),
+                             definite(&style.right, Some(scrollport.width)));
+        let b = self.dimensions.border_box();
+        let dx = stick_offset((b.x, b.width), (left, right), (scrollport.x, scrollport.width),
+                              (cb.x, cb.width));
+        let dy = stick_offset((b.y, b.height), (top, bottom), (scrollport.y, scrollport.height),
+                              (cb.y, cb.height));
+        self.translate(dx, dy);
+    }
+}
+
+// How far a sticky box spanning `(start, size)` along one axis moves to stay `near` from the
+// start and `far` from the end of the scrollport, limited to its containing block. The
+// scrollport and containing block are spans along the same axis.
+fn stick_offset((start, size): (f32, f32), (near, far): (Option<f32>, Option<f32>),
+                (view_start, view_size): (f32, f32), (cb_start, cb_size): (f32, f32)) -> f32 {
+    let end = start + size;
+    if let Some(near) = near {
+        let limit = view_start + near;
+        if start < limit {
+            return (limit - start).min(cb_start + cb_size - end).max(0.0);
+        }
+    }
+    if let Some(far) = far {
+        let limit = view_start + view_size - far;
+        if end > limit {
+            return -(end - limit).min(start - cb_start).max(0.0);
+        }
+    }
+    0.0
+}
+
+#[cfg(test)]
+mod tests {
+    use layout::tests::{lay_out, children, assert_near};
+
+    // A positioned box with 5px and 7px left and top borders and 10px of padding, which makes
+    // its padding box 420px by 220px at (5, 7).
+    const CONTAINER: &str = "* { display: block; } \
+                             #cb { position: relative; width: 400px; height: 200px; \
+                                   border-left-width: 5px; border-top-width: 7px; padding: 10px; }";
+
+    fn lay_out_in_container<F: Fn(&[f32], &[f32], &[f32])>(css: &str, f: F) {
+        lay_out("<div><div id=\"cb\"><p></p></div></div>", &format!("{} {}", CONTAINER, css),
+                |root| {
+            let cb = &root.children[0];
+            f(&children(cb, |c| c.x), &children(cb, |c| c.y), &children(cb, |c| c.width));
+        });
+    }
+
+    #[test]
+    fn absolute_boxes_are_placed_against_the_padding_box() {
+        lay_out_in_container("p { position: absolute; left: 20px; top: 30px; width: 50px; \
+                              height: 40px; }", |x, y, width| {
+            assert_near(x, &[25.0]);
+            assert_near(y, &[37.0]);
+            assert_near(width, &[50.0]);
+        });
+        lay_out_in_container("p { position: absolute; right: 20px; bottom: 30px; width: 50px; \
+                              height: 40px; }", |x, y, _| {
+            assert_near(x, &[355.0]);
+            assert_near(y, &[157.0]);
+        });
+        // Without a width, the box stretches between its offsets.
+        lay_out_in_container("p { position: absolute; left: 10%; right: 20px; top: 0; }",
+                             |x, _, width| {
+            assert_near(x, &[47.0]);
+            assert_near(width, &[358.0]);
+        });
+    }
+
+    #[test]
+    fn fixed_boxes_are_placed_against_the_viewport() {
+        lay_out_in_container("p { position: fixed; right: 0; bottom: 0; width: 100px; \
+                              height: 50px; }", |x, y, _| {
+            assert_near(x, &[700.0]);
+            assert_near(y, &[550.0]);
+        });
+    }
+
+    #[test]
+    fn relative_boxes_move_from_their_place_in_the_flow() {
+        lay_out("<div><p></p><p id=\"a\"></p><p id=\"b\"></p></div>",
+                "* { display: block; } p { height: 10px; } \
+                 #a { position: relative; left: 5px; top: 3px; } \
+                 #b { position: relative; right: 10%; bottom: 4px; }", |root| {
+            assert_near(&children(root, |c| c.x), &[0.0, 5.0, -80.0]);
+            assert_near(&children(root, |c| c.y), &[0.0, 13.0, 16.0]);
+        });
+    }
+
+    #[test]
+    fn sticky_boxes_stay_within_their_parent() {
+        let css = "* { display: block; } p { position: sticky; top: 100px; height: 80px; }";
+        lay_out("<div><div id=\"a\"><p></p></div><div id=\"b\"><p></p></div></div>",
+                &format!("{} #a {{ height: 150px; }} #b {{ height: 300px; }}", css), |root| {
+            // The first box is held back by the bottom of its parent.
+            assert_near(&children(&root.children[0], |c| c.y), &[70.0]);
+            // The second one starts below the top offset, so it stays in place.
+            assert_near(&children(&root.children[1], |c| c.y), &[150.0]);
+        });
+        lay_out("<div><div><p></p></div></div>", &format!("{} div {{ height: 300px; }}", css),
+                |root| {
+            assert_near(&children(&root.children[0], |c| c.y), &[100.0]);
+        });
+    }
+}
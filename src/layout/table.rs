@@ -0,0 +1,500 @@
+// Table layout: the CSS table model, with the anonymous boxes that complete the structure of a
+// table, and the automatic and fixed algorithms that size its columns.
+//
+// http://www.w3.org/TR/CSS2/tables.html
+//
+// There's no inline layout to measure content with, so cells contribute nothing to the width of
+// a column unless they set one; columns that no cell gives a width share the space left over.
+
+use super::{LayoutBox, BoxType, Dimensions, Rect, EdgeSizes, keyword};
+use style::TablePart;
+use css::Value::{Length, Calc, Pair};
+use properties::{InheritedStyle, ResetStyle};
+use dom::NodeType;
+use std::mem;
+
+// A cell's place in the grid of rows and columns.
+struct Slot {
+    row: usize,    // index of the row the cell starts in
+    cell: usize,   // index of the cell among the children of its row
+    column: usize, // index of the first column the cell spans
+    rows: usize,
+    columns: usize,
+}
+
+/// Wrap the children of a box that can't be its children in the table model in anonymous table
+/// parts. `parent` is the kind of table part the box is, or `None` if it's outside the table
+/// model.
+///
+/// http://www.w3.org/TR/CSS2/tables.html#anonymous-boxes
+pub(super) fn wrap_misparented<'a>(parent: Option<TablePart>, children: Vec<LayoutBox<'a>>)
+                                   -> Vec<LayoutBox<'a>> {
+    let wrapper = match parent {
+        Some(TablePart::Table) | Some(TablePart::RowGroup) => TablePart::Row,
+        Some(TablePart::Row) => TablePart::Cell,
+        _ => TablePart::Table
+    };
+    let mut wrapped = Vec::new();
+    let mut run = Vec::new();
+    for child in children {
+        // Absolutely positioned boxes are left where they are, out of the table's layout.
+        if child.is_out_of_flow() || can_contain(parent, child.table_part()) {
+            wrap(&mut wrapped, &mut run, wrapper);
+            wrapped.push(child);
+        } else {
+            run.push(child);
+        }
+    }
+    wrap(&mut wrapped, &mut run, wrapper);
+    wrapped
+}
+
+// Whether a table part (or a box outside the table model, for `None`) can contain another.
+fn can_contain(parent: Option<TablePart>, child: Option<TablePart>) -> bool {
+    match (parent, child) {
+        (Some(TablePart::Table), Some(TablePart::RowGroup)) |
+        (Some(TablePart::Table), Some(TablePart::Row)) |
+        (Some(TablePart::Table), Some(TablePart::Caption)) |
+        (Some(TablePart::RowGroup), Some(TablePart::Row)) |
+        (Some(TablePart::Row), Some(TablePart::Cell)) => true,
+        (Some(TablePart::Table), _) | (Some(TablePart::RowGroup), _) |
+        (Some(TablePart::Row), _) => false,
+        // Cells, captions and boxes outside the table model hold whole tables and other boxes.
+        (_, None) | (_, Some(TablePart::Table)) => true,
+        _ => false
+    }
+}
+
+// Move a run of misparented boxes into an anonymous table part of their own.
+fn wrap<'a>(wrapped: &mut Vec<LayoutBox<'a>>, run: &mut Vec<LayoutBox<'a>>, part: TablePart) {
+    if run.is_empty() {
+        return;
+    }
+    let mut anonymous = LayoutBox::new(BoxType::Table(part, None));
+    anonymous.children = wrap_misparented(Some(part), mem::take(run));
+    wrapped.push(anonymous);
+}
+
+impl<'a> LayoutBox<'a> {
+    /// Lay out the captions, rows and cells of a table. `height` is the height of the content
+    /// area if it's known up front.
+    ///
+    /// http://www.w3.org/TR/CSS2/tables.html#width-layout
+    ///
+    /// A table whose width is `auto` and whose columns all have a width shrinks to fit them.
+    /// Sets `self.dimensions.height` to the height of the captions and rows.
+    pub(super) fn layout_table(&mut self, height: Option<f32>) {
+        // Anonymous tables have the initial value of every property.
+        let (initial_inherited, initial_reset) = (InheritedStyle::default(), ResetStyle::default());
+        let (inherited, reset) = match self.style_node() {
+            Some(node) => (&*node.style().inherited, &*node.style().reset),
+            None => (&initial_inherited, &initial_reset)
+        };
+        let collapse = keyword(&inherited.border_collapse, "separate") == "collapse";
+        let (h_spacing, v_spacing) = match inherited.border_spacing {
+            _ if collapse => (0.0, 0.0),
+            Some(Pair(ref h, ref v)) => (h.to_px(), v.to_px()),
+            Some(ref spacing) => (spacing.to_px(), spacing.to_px()),
+            None => (0.0, 0.0)
+        };
+        let auto_width = !matches!(reset.width, Some(Length(..)) | Some(Calc(_)));
+        let fixed = keyword(&reset.table_layout, "auto") == "fixed" && !auto_width;
+
+        let rows = self.table_rows();
+        let slots = self.table_slots(&rows);
+        let column_count = slots.iter().map(|slot| slot.column + slot.columns).max().unwrap_or(0);
+
+        // Size the columns, and with them the table.
+        let table_width = self.dimensions.content.width;
+        let widths = {
+            let cells = slots.iter()
+                .filter(|slot| !fixed || slot.row == 0)
+                .map(|slot| {
+                    let cell = &row(&self.children, rows[slot.row]).children[slot.cell];
+                    (slot.column, slot.columns, cell.fixed_outer_width(table_width))
+                });
+            column_widths(cells, column_count, table_width, auto_width, h_spacing)
+        };
+        let inner_width = widths.iter().sum::<f32>() + h_spacing * (column_count + 1) as f32;
+        self.dimensions.content.width = inner_width;
+
+        // Lay out every cell across its columns to find the heights of the rows.
+        let borders = self.cell_borders(&rows, &slots, column_count, collapse);
+        let mut row_heights: Vec<f32> = rows.iter()
+            .map(|&path| row(&self.children, path).definite_height(inner_width, height))
+            .map(|height| height.unwrap_or(0.0))
+            .collect();
+        let mut cell_heights = Vec::with_capacity(slots.len());
+        for (slot, border) in slots.iter().zip(borders) {
+            let width = span_size(&widths[slot.column..slot.column + slot.columns], h_spacing);
+            let cell = &mut row_mut(&mut self.children, rows[slot.row]).children[slot.cell];
+            cell.dimensions = Default::default();
+            cell.calculate_edges(inner_width);
+            cell.dimensions.border = border;
+            cell.layout_within_edges(0.0, 0.0, width, None, inner_width, None);
+            cell_heights.push(cell.dimensions.border_box().height);
+        }
+        for (slot, &height) in slots.iter().zip(&cell_heights) {
+            if slot.rows == 1 {
+                row_heights[slot.row] = row_heights[slot.row].max(height);
+            }
+        }
+        // Cells spanning several rows share any extra height they need between them.
+        for (slot, &height) in slots.iter().zip(&cell_heights) {
+            let spanned = &mut row_heights[slot.row..slot.row + slot.rows];
+            let extra = height - span_size(spanned, v_spacing);
+            if slot.rows > 1 && extra > 0.0 {
+                for row_height in spanned.iter_mut() {
+                    *row_height += extra / slot.rows as f32;
+                }
+            }
+        }
+
+        // Captions go above the rows.
+        let content = self.dimensions.content;
+        let mut y = content.y;
+        for caption in &mut self.children {
+            if caption.table_part() == Some(TablePart::Caption) {
+                caption.layout_sized(content.x, y, inner_width, None, inner_width, None);
+                y += caption.dimensions.border_box().height;
+            }
+        }
+
+        // A table taller than its rows shares the extra height between them.
+        let rows_height = row_heights.iter().sum::<f32>() + v_spacing * (rows.len() + 1) as f32;
+        if let Some(height) = height {
+            let extra = height - (y - content.y) - rows_height;
+            if extra > 0.0 && !rows.is_empty() {
+                for row_height in &mut row_heights {
+                    *row_height += extra / rows.len() as f32;
+                }
+            }
+        }
+
+        // Place the rows, their groups and their cells.
+        let row_tops: Vec<f32> = row_heights.iter().scan(y + v_spacing, |top, height| {
+            let row_top = *top;
+            *top += height + v_spacing;
+            Some(row_top)
+        }).collect();
+        let column_lefts: Vec<f32> = widths.iter().scan(content.x + h_spacing, |left, width| {
+            let column_left = *left;
+            *left += width + h_spacing;
+            Some(column_left)
+        }).collect();
+        let row_rect = |first: usize, end: usize| Rect {
+            x: content.x + h_spacing,
+            y: if first < end { row_tops[first] } else { y },
+            width: (inner_width - 2.0 * h_spacing).max(0.0),
+            height: if first < end {
+                row_tops[end - 1] + row_heights[end - 1] - row_tops[first]
+            } else {
+                0.0
+            },
+        };
+        for (i, &path) in rows.iter().enumerate() {
+            row_mut(&mut self.children, path).dimensions = Dimensions {
+                content: row_rect(i, i + 1),
+                ..Default::default()
+            };
+        }
+        for (i, child) in self.children.iter_mut().enumerate() {
+            if child.table_part() == Some(TablePart::RowGroup) {
+                let first = rows.iter().position(|&(group, _)| group == i).unwrap_or(0);
+                let count = rows.iter().filter(|&&(group, _)| group == i).count();
+                child.dimensions = Dimensions {
+                    content: row_rect(first, first + count),
+                    ..Default::default()
+                };
+            }
+        }
+        for (slot, natural_height) in slots.iter().zip(cell_heights) {
+            let height = span_size(&row_heights[slot.row..slot.row + slot.rows], v_spacing);
+            let cell = &mut row_mut(&mut self.children, rows[slot.row]).children[slot.cell];
+            cell.translate(column_lefts[slot.column], row_tops[slot.row]);
+            cell.offset_relative(inner_width);
+            cell.align_cell_content(height - natural_height);
+        }
+
+        let captions_height = y - content.y;
+        self.dimensions.content.height = captions_height + row_heights.iter().sum::<f32>() +
+                                         v_spacing * (rows.len() + 1) as f32;
+    }
+
+    // The rows of the table in the order they're laid out, as the index of each row or row
+    // group among the table's children and the index of the row in its group. Header groups
+    // come first and footer groups last.
+    fn table_rows(&self) -> Vec<(usize, Option<usize>)> {
+        let rank = |child: &LayoutBox| match child.style_node() {
+            Some(node) if child.table_part() == Some(TablePart::RowGroup) => {
+                match keyword(&node.style().reset.display, "") {
+                    "table-header-group" => 0,
+                    "table-footer-group" => 2,
+                    _ => 1
+                }
+            }
+            _ => 1
+        };
+        let mut order: Vec<usize> = (0..self.children.len()).collect();
+        order.sort_by_key(|&i| rank(&self.children[i]));
+
+        let mut rows = Vec::new();
+        for i in order {
+            match self.children[i].table_part() {
+                Some(TablePart::RowGroup) => {
+                    rows.extend((0..self.children[i].children.len()).map(|row| (i, Some(row))))
+                }
+                Some(TablePart::Row) => rows.push((i, None)),
+                _ => {}
+            }
+        }
+        rows
+    }
+
+    // Find the rows and columns each cell spans, from its `colspan` and `rowspan` attributes.
+    // Cells fill the first free columns of their row, after any spanning down from above.
+    fn table_slots(&self, rows: &[(usize, Option<usize>)]) -> Vec<Slot> {
+        let mut slots = Vec::new();
+        let mut taken: Vec<Vec<bool>> = vec![Vec::new(); rows.len()];
+        for (r, &path) in rows.iter().enumerate() {
+            // A row span stops at the end of the row group, and `rowspan="0"` reaches it.
+            let group_end = match path {
+                (group, Some(_)) => r + rows[r..].iter().take_while(|row| row.0 == group).count(),
+                (_, None) => r + rows[r..].iter().take_while(|row| row.1.is_none()).count()
+            };
+            let mut column = 0;
+            for (c, cell) in row(&self.children, path).children.iter().enumerate() {
+                while taken[r].get(column).cloned().unwrap_or(false) {
+                    column += 1;
+                }
+                let columns = span(cell, "colspan", 1).clamp(1, 1000);
+                let rows = match span(cell, "rowspan", 1) {
+                    0 => group_end - r,
+                    rows => rows.min(group_end - r)
+                };
+                for taken in &mut taken[r..r + rows] {
+                    if taken.len() < column + columns {
+                        taken.resize(column + columns, false);
+                    }
+                    for slot in &mut taken[column..column + columns] {
+                        *slot = true;
+                    }
+                }
+                slots.push(Slot { row: r, cell: c, column, rows, columns });
+                column += columns;
+            }
+        }
+        slots
+    }
+
+    // The border widths of each cell. With `border-collapse: collapse`, the borders of
+    // neighbouring cells merge into one as wide as the wider of the two, and each cell draws half
+    // of it. Borders at the edge of the table are the cells' own.
+    fn cell_borders(&mut self, rows: &[(usize, Option<usize>)], slots: &[Slot],
+                    column_count: usize, collapse: bool) -> Vec<EdgeSizes> {
+        let width = self.dimensions.content.width;
+        let borders: Vec<EdgeSizes> = slots.iter().map(|slot| {
+            let cell = &mut row_mut(&mut self.children, rows[slot.row]).children[slot.cell];
+            cell.calculate_edges(width);
+            cell.dimensions.border
+        }).collect();
+        if !collapse {
+            return borders;
+        }
+
+        let mut owners = vec![vec![None; column_count]; rows.len()];
+        for (i, slot) in slots.iter().enumerate() {
+            for row in &mut owners[slot.row..slot.row + slot.rows] {
+                for owner in &mut row[slot.column..slot.column + slot.columns] {
+                    *owner = Some(i);
+                }
+            }
+        }
+        let neighbour = |row: usize, column: usize| owners[row][column].map(|i| borders[i]);
+        slots.iter().zip(&borders).map(|(slot, own)| {
+            let last_row = slot.row + slot.rows - 1;
+            let last_column = slot.column + slot.columns - 1;
+            let mut border = *own;
+            if slot.column > 0 {
+                if let Some(other) = neighbour(slot.row, slot.column - 1) {
+                    border.left = own.left.max(other.right) / 2.0;
+                }
+            }
+            if last_column + 1 < column_count {
+                if let Some(other) = neighbour(slot.row, last_column + 1) {
+                    border.right = own.right.max(other.left) / 2.0;
+                }
+            }
+            if slot.row > 0 {
+                if let Some(other) = neighbour(slot.row - 1, slot.column) {
+                    border.top = own.top.max(other.bottom) / 2.0;
+                }
+            }
+            if last_row + 1 < rows.len() {
+                if let Some(other) = neighbour(last_row + 1, slot.column) {
+                    border.bottom = own.bottom.max(other.top) / 2.0;
+                }
+            }
+            border
+        }).collect()
+    }
+
+    // Grow a cell laid out at its natural height by `extra` to fill its rows, moving its
+    // contents down as its `vertical-align` says. `baseline` is treated as `top`.
+    fn align_cell_content(&mut self, extra: f32) {
+        let offset = match self.style_node() {
+            Some(node) => match keyword(&node.style().reset.vertical_align, "baseline") {
+                "middle" => extra / 2.0,
+                "bottom" => extra,
+                _ => 0.0
+            },
+            None => 0.0
+        };
+        for child in &mut self.children {
+            child.translate(0.0, offset);
+        }
+        self.dimensions.content.height += extra.max(0.0);
+    }
+}
+
+fn row<'b, 'a>(children: &'b [LayoutBox<'a>], path: (usize, Option<usize>)) -> &'b LayoutBox<'a> {
+    match path {
+        (child, None) => &children[child],
+        (group, Some(row)) => &children[group].children[row]
+    }
+}
+
+fn row_mut<'b, 'a>(children: &'b mut [LayoutBox<'a>], path: (usize, Option<usize>))
+                   -> &'b mut LayoutBox<'a> {
+    match path {
+        (child, None) => &mut children[child],
+        (group, Some(row)) => &mut children[group].children[row]
+    }
+}
+
+// The value of a cell's `colspan` or `rowspan` attribute, or `default` if it has none.
+fn span(cell: &LayoutBox, attribute: &str, default: usize) -> usize {
+    match cell.style_node().map(|node| &node.node.node_type) {
+        Some(NodeType::Element(elem)) => elem.attributes.get(attribute)
+            .and_then(|value| value.trim().parse().ok())
+            .unwrap_or(default),
+        _ => default
+    }
+}
+
+// The size of a span of rows or columns, with the spacing between them.
+fn span_size(sizes: &[f32], spacing: f32) -> f32 {
+    sizes.iter().sum::<f32>() + spacing * (sizes.len().max(1) - 1) as f32
+}
+
+// The widths of the columns, from the (first column, columns spanned, outer width) of the cells
+// that set a width. Columns that no cell sets a width for share the space the others leave; if
+// every column has a width, they grow in proportion to fill a table whose width is set.
+fn column_widths<I>(cells: I, count: usize, table_width: f32, auto_width: bool, spacing: f32)
+                    -> Vec<f32>
+    where I: Iterator<Item=(usize, usize, Option<f32>)>
+{
+    let mut cells: Vec<(usize, usize, f32)> = cells
+        .filter_map(|(column, columns, width)| width.map(|width| (column, columns, width)))
+        .collect();
+    cells.sort_by_key(|&(_, columns, _)| columns);
+
+    let mut widths: Vec<Option<f32>> = vec![None; count];
+    for (column, columns, width) in cells {
+        let spanned = &mut widths[column..column + columns];
+        let current = spanned.iter().map(|width| width.unwrap_or(0.0)).sum::<f32>() +
+                      spacing * (columns - 1) as f32;
+        if width <= current && spanned.iter().all(Option::is_some) {
+            continue;
+        }
+        // A spanning cell widens the columns without a width first.
+        let autos = spanned.iter().filter(|width| width.is_none()).count();
+        let extra = (width - current).max(0.0);
+        for spanned_width in spanned.iter_mut() {
+            match *spanned_width {
+                None => *spanned_width = Some(extra / autos as f32),
+                Some(ref mut w) if autos == 0 => *w += extra / columns as f32,
+                Some(_) => {}
+            }
+        }
+    }
+
+    let fixed: f32 = widths.iter().filter_map(|&width| width).sum();
+    let autos = widths.iter().filter(|width| width.is_none()).count();
+    let spacing = spacing * (count + 1) as f32;
+    let inner = if auto_width && autos == 0 {
+        fixed + spacing
+    } else {
+        table_width.max(fixed + spacing)
+    };
+    let free = inner - fixed - spacing;
+    widths.iter().map(|&width| match width {
+        None => free / autos as f32,
+        Some(width) if autos == 0 && fixed > 0.0 => width + free * width / fixed,
+        Some(width) if autos == 0 => width + free / count as f32,
+        Some(width) => width
+    }).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::column_widths;
+    use layout::tests::{lay_out, children, assert_near};
+
+    fn widths(cells: &[(usize, usize, Option<f32>)], count: usize, table_width: f32,
+              auto_width: bool, spacing: f32) -> Vec<f32> {
+        column_widths(cells.iter().cloned(), count, table_width, auto_width, spacing)
+    }
+
+    #[test]
+    fn columns_without_a_width_share_the_rest() {
+        assert_near(&widths(&[(0, 1, Some(100.0)), (1, 1, None), (2, 1, None)], 3, 400.0, false,
+                            0.0),
+                    &[100.0, 150.0, 150.0]);
+        assert_near(&widths(&[(0, 1, Some(100.0))], 2, 400.0, true, 10.0), &[100.0, 270.0]);
+    }
+
+    #[test]
+    fn columns_with_widths_fill_a_table_with_a_width() {
+        assert_near(&widths(&[(0, 1, Some(100.0)), (1, 1, Some(50.0))], 2, 300.0, false, 0.0),
+                    &[200.0, 100.0]);
+        // A table whose width is `auto` shrinks to fit them instead.
+        assert_near(&widths(&[(0, 1, Some(100.0)), (1, 1, Some(50.0))], 2, 800.0, true, 0.0),
+                    &[100.0, 50.0]);
+    }
+
+    #[test]
+    fn spanning_cells_widen_the_columns_they_span() {
+        // Columns without a width take the extra first.
+        assert_near(&widths(&[(0, 1, Some(100.0)), (0, 2, Some(300.0))], 2, 0.0, true, 10.0),
+                    &[100.0, 190.0]);
+        // Otherwise every spanned column takes an equal share.
+        assert_near(&widths(&[(0, 1, Some(100.0)), (1, 1, Some(100.0)), (0, 2, Some(300.0))], 2,
+                            0.0, true, 0.0),
+                    &[150.0, 150.0]);
+        // A span that's narrow enough already changes nothing.
+        assert_near(&widths(&[(0, 1, Some(100.0)), (1, 1, Some(100.0)), (0, 2, Some(150.0))], 2,
+                            0.0, true, 0.0),
+                    &[100.0, 100.0]);
+    }
+
+    #[test]
+    fn cells_are_placed_in_their_columns_and_rows() {
+        lay_out("<table><tr><td id=\"a\"></td><td></td></tr><tr><td></td><td id=\"b\"></td></tr>\
+                 </table>",
+                "table { display: table; width: 300px; border-spacing: 10px; } \
+                 tr { display: table-row; } td { display: table-cell; } \
+                 #a { width: 100px; } #b { height: 40px; }", |table| {
+            let rows: Vec<_> = table.children.iter()
+                .map(|row| children(row, |c| c.x))
+                .collect();
+            assert_near(&rows[0], &[10.0, 120.0]);
+            assert_near(&rows[1], &[10.0, 120.0]);
+            assert_near(&children(&table.children[1], |c| c.width), &[100.0, 170.0]);
+            assert_near(&children(&table.children[1], |c| c.y), &[20.0, 20.0]);
+            // Every cell in a row is as tall as the row.
+            assert_near(&children(&table.children[1], |c| c.height), &[40.0, 40.0]);
+            assert_near(&[table.dimensions.content.height], &[70.0]);
+        });
+    }
+}
@@ -0,0 +1,16 @@
+// The rendering engine, shared by the `pareidolia` binary and the examples.
+
+pub mod dom;
+pub mod html;
+pub mod css;
+pub mod fonts;
+pub mod loader;
+pub mod properties;
+pub mod media;
+pub mod bloom;
+pub mod selector_map;
+pub mod style;
+pub mod variables;
+pub mod generated;
+pub mod layout;
+pub mod painting;
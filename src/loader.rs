@@ -0,0 +1,119 @@
+// Loading stylesheets from files, following `@import` rules and registering `@font-face`
+// sources.
+
+use css::{self, Origin, Stylesheet};
+use fonts::{FontFace, FontRegistry};
+use media::MediaQueryList;
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+
+/// Load the stylesheet at `path`, with its imports inlined in place of its `@import` rules.
+/// Returns a message saying what went wrong if the file can't be read.
+///
+/// Imports are resolved relative to the importing file, and a file that is already being
+/// imported further up the chain is skipped to break cycles. An import that can't be read is
+/// reported and skipped. The rules of an import with media queries only apply when those
+/// queries match. Fonts from `@font-face` rules in any of the files are added to `fonts`, and
+/// likewise can only be selected when the queries of the imports they came through match.
+pub fn load_stylesheet(path: &Path, origin: Origin, fonts: &mut FontRegistry)
+                       -> Result<Stylesheet, String> {
+    load(path, origin, fonts, &mut Vec::new(), &[])
+}
+
+// `media` holds the queries of the imports that led to `path`, outermost first.
+fn load(path: &Path, origin: Origin, fonts: &mut FontRegistry, stack: &mut Vec<PathBuf>,
+        media: &[MediaQueryList]) -> Result<Stylesheet, String> {
+    let error = |e: io::Error| format!("Could not load stylesheet {}: {}", path.display(), e);
+    let canonical = fs::canonicalize(path).map_err(error)?;
+    if stack.contains(&canonical) {
+        return Err(format!("Skipping circular import of {}", path.display()));
+    }
+
+    let mut source = String::new();
+    File::open(&canonical).and_then(|mut file| file.read_to_string(&mut source)).map_err(error)?;
+    let mut stylesheet = css::parse(source, origin);
+    let base = canonical.parent().map(Path::to_path_buf).unwrap_or_default();
+
+    for font_face in &stylesheet.font_faces {
+        register_font(font_face, &base, media, fonts);
+    }
+
+    // Imported rules come before the importing stylesheet's own rules.
+    stack.push(canonical.clone());
+    let mut rules = Vec::new();
+    for import in stylesheet.imports.drain(..) {
+        let mut import_media = media.to_vec();
+        if !import.media.queries.is_empty() {
+            import_media.push(import.media.clone());
+        }
+        let imported = match load(&base.join(&import.url), origin, fonts, stack, &import_media) {
+            Ok(imported) => imported,
+            Err(message) => {
+                eprintln!("{}", message);
+                continue;
+            }
+        };
+        for mut rule in imported.rules {
+            if !import.media.queries.is_empty() {
+                rule.media.insert(0, import.media.clone());
+            }
+            rules.push(rule);
+        }
+    }
+    stack.pop();
+
+    rules.append(&mut stylesheet.rules);
+    stylesheet.rules = rules;
+    Ok(stylesheet)
+}
+
+// Register the first `src` of a font face that can be read from a local file.
+fn register_font(font_face: &css::FontFaceRule, base: &Path, media: &[MediaQueryList],
+                 fonts: &mut FontRegistry) {
+    for source in &font_face.sources {
+        let path = base.join(source);
+        if let Ok(data) = fs::read(&path) {
+            fonts.register(FontFace {
+                family: font_face.family.clone(),
+                weight: font_face.weight,
+                italic: font_face.italic,
+                path,
+                data,
+                media: media.to_vec(),
+            });
+            return;
+        }
+    }
+    eprintln!("No local source found for font {}", font_face.family);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use media::{Device, MediaType};
+    use std::env;
+    use std::process;
+
+    #[test]
+    fn fonts_follow_the_media_of_their_imports() {
+        let dir = env::temp_dir().join(format!("pareidolia-loader-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let files = [("main.css", "@import \"print.css\" print; p { color: red; }"),
+                     ("print.css", "@font-face { font-family: Brand; src: url(brand.woff); }"),
+                     ("brand.woff", "font")];
+        for &(name, contents) in &files {
+            fs::write(dir.join(name), contents).unwrap();
+        }
+
+        let mut fonts = FontRegistry::new();
+        let loaded = load_stylesheet(&dir.join("main.css"), Origin::Author, &mut fonts);
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(loaded.unwrap().rules.len(), 1);
+        assert_eq!(fonts.faces.len(), 1);
+        let screen = Device::new(MediaType::Screen, Default::default());
+        let print = Device::new(MediaType::Print, Default::default());
+        assert!(fonts.select("Brand", 400, false, &screen).is_none());
+        assert!(fonts.select("Brand", 400, false, &print).is_some());
+    }
+}
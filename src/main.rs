@@ -1,27 +1,39 @@
 extern crate getopts;
 extern crate image;
+extern crate pareidolia;
 
-pub mod dom;
-pub mod html;
-pub mod css;
-pub mod style;
-pub mod layout;
-pub mod painting;
+use pareidolia::{dom, html, css, fonts, loader, media, style, layout, painting};
 
 use std::fs::File;
 use std::io::{Read, BufWriter};
+use std::path::Path;
+use std::process;
 
 fn main() {
     println!("Lanch Pareidolia\n");
 
     let mut opts = getopts::Options::new();
     opts.optopt("h", "html", "HTML document", "FILENAME");
+    opts.optopt("c", "css", "CSS stylesheet", "FILENAME");
+    opts.optopt("u", "user-css", "User CSS stylesheet", "FILENAME");
+    opts.optopt("m", "media", "Media type to render for: screen or print", "TYPE");
+    opts.optflag("d", "dark", "Prefer a dark color scheme");
+    opts.optopt("j", "jobs", "Compute styles on N threads", "N");
 
-    let matches = opts.parse(std::env::args().skip(1)).unwrap();
+    let matches = match opts.parse(std::env::args().skip(1)) {
+        Ok(matches) => matches,
+        Err(e) => fail(&e.to_string())
+    };
     let str_arg = |flag: &str, default: &str| -> String {
         matches.opt_str(flag).unwrap_or(default.to_string())
     };
 
+    let traversal = match str_arg("j", "1").parse() {
+        Ok(1) => style::Traversal::Sequential,
+        Ok(threads) if threads > 1 => style::Traversal::Parallel(threads),
+        _ => fail("-j takes a number of threads, at least 1")
+    };
+
     // Since we don't have an actual window, hard-code the "viewport" size.
     let mut viewport: layout::Dimensions = Default::default();
     viewport.content.width  = 800.0;
@@ -32,14 +44,32 @@ fn main() {
 
     // Parsing
     let root_node = html::parse(html);
-    let stylesheet = css::example();
-    let styled = style::style_tree(&root_node, &stylesheet);
+    let mut fonts = fonts::FontRegistry::new();
+    let mut stylesheets = vec![css::user_agent()];
+    if let Some(filename) = matches.opt_str("u") {
+        stylesheets.push(load_stylesheet(&filename, css::Origin::User, &mut fonts));
+    }
+    stylesheets.push(match matches.opt_str("c") {
+        Some(filename) => load_stylesheet(&filename, css::Origin::Author, &mut fonts),
+        None => css::example()
+    });
+    let mut device = media::Device::new(match &*str_arg("m", "screen") {
+        "print" => media::MediaType::Print,
+        _ => media::MediaType::Screen
+    }, viewport);
+    if matches.opt_present("d") {
+        device.color_scheme = media::ColorScheme::Dark;
+    }
+    let (styled, sharing) = style::style_tree_with_stats(&root_node, &stylesheets, &device,
+                                                            traversal);
     let layout = layout::layout_tree(&styled, viewport);
     let display_list = painting::build_display_list(&layout);
 
     // Print for simple visualization
     dom::print(root_node.clone());
+    fonts::print(&fonts);
     style::print(styled.clone());
+    style::print_sharing(&sharing);
     layout::print(layout.clone());
     painting::print(display_list.clone());
 
@@ -57,8 +87,25 @@ fn main() {
     println!("Saved output as {}", filename);
 }
 
+// Print what went wrong with the command line or its files, and exit.
+fn fail(message: &str) -> ! {
+    eprintln!("{}", message);
+    process::exit(1);
+}
+
+// Load a stylesheet named on the command line, or exit with the reason it couldn't be loaded.
+fn load_stylesheet(filename: &str, origin: css::Origin, fonts: &mut fonts::FontRegistry)
+                   -> css::Stylesheet {
+    match loader::load_stylesheet(Path::new(filename), origin, fonts) {
+        Ok(stylesheet) => stylesheet,
+        Err(message) => fail(&message)
+    }
+}
+
 fn read_source(filename: String) -> String {
     let mut str = String::new();
-    File::open(filename).unwrap().read_to_string(&mut str).unwrap();
+    if let Err(e) = File::open(&filename).and_then(|mut file| file.read_to_string(&mut str)) {
+        fail(&format!("Could not read {}: {}", filename, e));
+    }
     str
 }
@@ -0,0 +1,215 @@
+// Media queries, and the device they are evaluated against.
+//
+// http://www.w3.org/TR/mediaqueries-4/
+
+use layout::Dimensions;
+
+/// The output device a document is being styled for.
+#[derive(Clone, Copy)]
+pub struct Device {
+    pub media_type: MediaType,
+    pub viewport: Dimensions,
+    pub resolution: f32, // device pixels per CSS px
+    pub color_scheme: ColorScheme,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum MediaType {
+    All,
+    Screen,
+    Print,
+    Unknown,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum ColorScheme {
+    Light,
+    Dark,
+}
+
+// A comma-separated list of queries, which matches if any of them does.
+#[derive(Clone)]
+pub struct MediaQueryList {
+    pub queries: Vec<MediaQuery>,
+}
+
+// e.g. `not screen and (min-width: 600px)`
+#[derive(Clone)]
+pub struct MediaQuery {
+    pub negated: bool,
+    pub media_type: MediaType,
+    pub features: Vec<MediaFeature>,
+}
+
+#[derive(Clone)]
+pub enum MediaFeature {
+    Width(Range),      // in px
+    Height(Range),     // in px
+    Resolution(Range), // in dppx
+    Orientation(Orientation),
+    PrefersColorScheme(ColorScheme),
+    Unknown,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Orientation {
+    Portrait,
+    Landscape,
+}
+
+// The values a range feature accepts.
+#[derive(Clone, Copy)]
+pub enum Range {
+    Min(f32),       // `min-` prefix or `>=`
+    Max(f32),       // `max-` prefix or `<=`
+    Exact(f32),     // no prefix or `=`
+    Greater(f32),   // `>`
+    Less(f32),      // `<`
+    NonZero,        // boolean context, e.g. `(width)`
+}
+
+impl Device {
+    /// A device preferring a light color scheme, with one device pixel per CSS px.
+    pub fn new(media_type: MediaType, viewport: Dimensions) -> Device {
+        Device {
+            media_type,
+            viewport,
+            resolution: 1.0,
+            color_scheme: ColorScheme::Light,
+        }
+    }
+
+    fn orientation(&self) -> Orientation {
+        if self.viewport.content.height >= self.viewport.content.width {
+            Orientation::Portrait
+        } else {
+            Orientation::Landscape
+        }
+    }
+}
+
+impl MediaQueryList {
+    /// An empty list matches every device.
+    pub fn matches(&self, device: &Device) -> bool {
+        self.queries.is_empty() || self.queries.iter().any(|query| query.matches(device))
+    }
+}
+
+impl MediaQuery {
+    pub fn matches(&self, device: &Device) -> bool {
+        let type_matches = match self.media_type {
+            MediaType::All => true,
+            MediaType::Unknown => false,
+            media_type => media_type == device.media_type
+        };
+        let matches = type_matches && self.features.iter().all(|feature| feature.matches(device));
+        matches != self.negated
+    }
+}
+
+impl MediaFeature {
+    pub fn matches(&self, device: &Device) -> bool {
+        match *self {
+            MediaFeature::Width(range) => range.contains(device.viewport.content.width),
+            MediaFeature::Height(range) => range.contains(device.viewport.content.height),
+            MediaFeature::Resolution(range) => range.contains(device.resolution),
+            MediaFeature::Orientation(orientation) => orientation == device.orientation(),
+            MediaFeature::PrefersColorScheme(scheme) => scheme == device.color_scheme,
+            MediaFeature::Unknown => false
+        }
+    }
+}
+
+impl Range {
+    pub fn contains(&self, value: f32) -> bool {
+        match *self {
+            Range::Min(min) => value >= min,
+            Range::Max(max) => value <= max,
+            Range::Exact(v) => value == v,
+            Range::Greater(min) => value > min,
+            Range::Less(max) => value < max,
+            Range::NonZero => value != 0.0
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use css::{self, Origin};
+
+    fn screen(width: f32, height: f32) -> Device {
+        let mut viewport: Dimensions = Default::default();
+        viewport.content.width = width;
+        viewport.content.height = height;
+        Device::new(MediaType::Screen, viewport)
+    }
+
+    fn matches(queries: &str, device: &Device) -> bool {
+        let sheet = css::parse(format!("@media {} {{ p {{ }} }}", queries), Origin::Author);
+        sheet.rules[0].media[0].matches(device)
+    }
+
+    #[test]
+    fn media_types() {
+        let device = screen(800.0, 600.0);
+        assert!(matches("screen", &device));
+        assert!(matches("all", &device));
+        assert!(matches("only screen", &device));
+        assert!(!matches("print", &device));
+        assert!(matches("not print", &device));
+        assert!(!matches("tv", &device));
+        assert!(matches("not tv", &device));
+        assert!(matches("", &device));
+    }
+
+    #[test]
+    fn width_and_height() {
+        let device = screen(800.0, 600.0);
+        assert!(matches("(min-width: 600px)", &device));
+        assert!(!matches("(max-width: 600px)", &device));
+        assert!(matches("(min-width: 50em)", &device));
+        assert!(matches("(width >= 800px)", &device));
+        assert!(!matches("(width > 800px)", &device));
+        assert!(matches("(width: 800px)", &device));
+        assert!(matches("(height < 601px)", &device));
+        assert!(matches("(width)", &device));
+        assert!(!matches("(height)", &screen(800.0, 0.0)));
+    }
+
+    #[test]
+    fn other_features() {
+        let mut device = screen(800.0, 600.0);
+        assert!(matches("(orientation: landscape)", &device));
+        assert!(!matches("(orientation: portrait)", &device));
+        assert!(matches("(resolution: 96dpi)", &device));
+        assert!(!matches("(min-resolution: 2dppx)", &device));
+        assert!(matches("(prefers-color-scheme: light)", &device));
+        device.resolution = 2.0;
+        device.color_scheme = ColorScheme::Dark;
+        assert!(matches("(min-resolution: 2x)", &device));
+        assert!(matches("(prefers-color-scheme: dark)", &device));
+        assert!(!matches("(hover: hover)", &device));
+    }
+
+    #[test]
+    fn combined_queries() {
+        let device = screen(800.0, 600.0);
+        assert!(matches("screen and (min-width: 600px) and (orientation: landscape)", &device));
+        assert!(!matches("screen and (min-width: 600px) and (orientation: portrait)", &device));
+        assert!(matches("not screen and (max-width: 600px)", &device));
+        assert!(matches("print, (max-width: 100px), screen", &device));
+        assert!(!matches("print, (max-width: 100px)", &device));
+    }
+
+    #[test]
+    fn nested_blocks_must_all_match() {
+        let source = "@media screen { @media (max-width: 600px) { p { } } \
+                                      @media (min-width: 600px) { a { } } }";
+        let sheet = css::parse(String::from(source), Origin::Author);
+        let device = screen(800.0, 600.0);
+        let applies = |i: usize| sheet.rules[i].media.iter().all(|media| media.matches(&device));
+        assert!(!applies(0));
+        assert!(applies(1));
+    }
+}
@@ -1,5 +1,6 @@
 use css::{Color, Value};
-use layout::{Rect, LayoutBox, BoxType, AnonymousBlock};
+use style::ComputedStyle;
+use layout::{Rect, LayoutBox};
 
 type DisplayList = Vec<DisplayCommand>;
 
@@ -17,79 +18,199 @@ pub struct Canvas {
 
 pub fn build_display_list(layout_root: &LayoutBox) -> DisplayList {
     let mut list = Vec::new();
-    render_layout_box(&mut list, layout_root);
+    render_stacking_context(&mut list, layout_root);
     return list;
 }
 
-fn render_layout_box(list: &mut DisplayList, layout_box: &LayoutBox) {
+/// The descendants of a stacking context that it paints, sorted into the layers it paints them
+/// in, each in tree order.
+///
+/// http://www.w3.org/TR/CSS2/zindex.html
+#[derive(Default)]
+struct Layers<'b, 'a: 'b> {
+    negative: Vec<&'b LayoutBox<'a>>,   // stacking contexts with a negative `z-index`
+    blocks: Vec<&'b LayoutBox<'a>>,     // boxes in the flow that aren't positioned
+    floats: Vec<&'b LayoutBox<'a>>,     // floats that aren't positioned
+    positioned: Vec<&'b LayoutBox<'a>>, // positioned boxes and stacking contexts at `z-index` 0
+    positive: Vec<&'b LayoutBox<'a>>,   // stacking contexts with a positive `z-index`
+}
+
+impl<'b, 'a> Layers<'b, 'a> {
+    /// Sort a box and the descendants it doesn't paint itself into layers. Floats and positioned
+    /// boxes paint their own blocks and floats, so below them (once `in_flow` is false) only
+    /// positioned boxes and stacking contexts are collected.
+    fn collect(&mut self, layout_box: &'b LayoutBox<'a>, in_flow: bool) {
+        if is_stacking_context(layout_box) {
+            match layout_box.z_index().unwrap_or(0) {
+                z if z < 0 => self.negative.push(layout_box),
+                0 => self.positioned.push(layout_box),
+                _ => self.positive.push(layout_box)
+            }
+            return;
+        }
+        let in_flow = if layout_box.is_positioned() {
+            self.positioned.push(layout_box);
+            false
+        } else if layout_box.is_float() {
+            if in_flow {
+                self.floats.push(layout_box);
+            }
+            false
+        } else {
+            if in_flow {
+                self.blocks.push(layout_box);
+            }
+            in_flow
+        };
+        for child in &layout_box.children {
+            self.collect(child, in_flow);
+        }
+    }
+}
+
+// Whether a box forms a stacking context of its own: if it's positioned with a `z-index`, fixed
+// or sticky, or has an `opacity` below 1 or a `transform`.
+fn is_stacking_context(layout_box: &LayoutBox) -> bool {
+    let style = match layout_box.style_node() {
+        Some(node) => &node.style().reset,
+        None => return false
+    };
+    let positioned = match layout_box.position() {
+        "fixed" | "sticky" => return true,
+        "static" => false,
+        _ => true
+    };
+    positioned && layout_box.z_index().is_some() ||
+        match style.opacity {
+            Some(Value::Number(opacity)) => opacity < 1.0,
+            _ => false
+        } ||
+        matches!(style.transform, Some(Value::Transform(_)))
+}
+
+/// Paint a stacking context: its own background and borders, then its descendants layer by
+/// layer, from the stacking contexts below it to those above.
+fn render_stacking_context(list: &mut DisplayList, layout_box: &LayoutBox) {
+    render_background(list, layout_box);
+    render_borders(list, layout_box);
+
+    let mut layers = Layers::default();
+    for child in &layout_box.children {
+        layers.collect(child, true);
+    }
+    layers.negative.sort_by_key(|context| context.z_index());
+    layers.positive.sort_by_key(|context| context.z_index());
+
+    for context in &layers.negative {
+        render_stacking_context(list, context);
+    }
+    render_flow(list, &layers);
+    for layout_box in layers.positioned {
+        if is_stacking_context(layout_box) {
+            render_stacking_context(list, layout_box);
+        } else {
+            render_pseudo_context(list, layout_box);
+        }
+    }
+    for context in layers.positive {
+        render_stacking_context(list, context);
+    }
+}
+
+/// Paint a float or a positioned box with `z-index: auto` as if it formed a stacking context,
+/// except for the positioned boxes and stacking contexts in it, which its enclosing stacking
+/// context paints.
+fn render_pseudo_context(list: &mut DisplayList, layout_box: &LayoutBox) {
     render_background(list, layout_box);
     render_borders(list, layout_box);
 
+    let mut layers = Layers::default();
     for child in &layout_box.children {
-        render_layout_box(list, child);
+        layers.collect(child, true);
+    }
+    render_flow(list, &layers);
+}
+
+// Paint the blocks in the flow of a stacking context, then its floats.
+fn render_flow(list: &mut DisplayList, layers: &Layers) {
+    for layout_box in &layers.blocks {
+        render_background(list, layout_box);
+        render_borders(list, layout_box);
+    }
+    for layout_box in &layers.floats {
+        render_pseudo_context(list, layout_box);
     }
 }
 
 fn render_background(list: &mut DisplayList, layout_box: &LayoutBox) {
-    get_color(layout_box, "background").map(|color|
-        list.push(DisplayCommand::SolidColor(color, layout_box.dimensions.border_box())));
+    if let Some(color) = get_color(layout_box, |style| &style.reset.background_color) {
+        list.push(DisplayCommand::SolidColor(color, layout_box.dimensions.border_box()));
+    }
 
     // Render color as background until we can render more than Rects
-    get_color(layout_box, "color").map(|color|
-        list.push(DisplayCommand::SolidColor(color, layout_box.dimensions.border_box())));
+    if let Some(color) = get_color(layout_box, |style| &style.inherited.color) {
+        list.push(DisplayCommand::SolidColor(color, layout_box.dimensions.border_box()));
+    }
 }
 
-fn get_color(layout_box: &LayoutBox, name: &str) -> Option<Color> {
-    match layout_box.box_type {
-        BoxType::Vertical(style) => match style.value(name) {
+// The color `property` picks out of the box's computed style, if it has one.
+fn get_color<F>(layout_box: &LayoutBox, property: F) -> Option<Color>
+    where F: Fn(&ComputedStyle) -> &Option<Value>
+{
+    match layout_box.style_node() {
+        Some(style) => match *property(style.style()) {
             Some(Value::ColorValue(color)) => Some(color),
             _ => None
         },
-        BoxType::Horizontal(_) => None,
-        AnonymousBlock => None
+        None => None
     }
 }
 
 fn render_borders(list: &mut DisplayList, layout_box: &LayoutBox) {
-    let color = match get_color(layout_box, "border-color") {
-        Some(color) => color,
-        _ => return // bail out if no border-color is specified
-    };
-
     let d = &layout_box.dimensions;
     let border_box = d.border_box();
 
+    // Each side is skipped if it has no border color specified.
+
     // Left border
-    list.push(DisplayCommand::SolidColor(color, Rect {
-        x: border_box.x,
-        y: border_box.y,
-        width: d.border.left,
-        height: border_box.height,
-    }));
+    if let Some(color) = get_color(layout_box, |style| &style.reset.border_left_color) {
+        list.push(DisplayCommand::SolidColor(color, Rect {
+            x: border_box.x,
+            y: border_box.y,
+            width: d.border.left,
+            height: border_box.height,
+        }));
+    }
 
     // Right border
-    list.push(DisplayCommand::SolidColor(color, Rect {
-        x: border_box.x + border_box.width - d.border.right,
-        y: border_box.y,
-        width: d.border.right,
-        height: border_box.height,
-    }));
+    if let Some(color) = get_color(layout_box, |style| &style.reset.border_right_color) {
+        list.push(DisplayCommand::SolidColor(color, Rect {
+            x: border_box.x + border_box.width - d.border.right,
+            y: border_box.y,
+            width: d.border.right,
+            height: border_box.height,
+        }));
+    }
 
     // Top border
-    list.push(DisplayCommand::SolidColor(color, Rect {
-        x: border_box.x,
-        y: border_box.y,
-        width: border_box.width,
-        height: d.border.top,
-    }));
+    if let Some(color) = get_color(layout_box, |style| &style.reset.border_top_color) {
+        list.push(DisplayCommand::SolidColor(color, Rect {
+            x: border_box.x,
+            y: border_box.y,
+            width: border_box.width,
+            height: d.border.top,
+        }));
+    }
 
     // Bottom border
-    list.push(DisplayCommand::SolidColor(color, Rect {
-        x: border_box.x,
-        y: border_box.y + border_box.height - d.border.bottom,
-        width: border_box.width,
-        height: d.border.bottom,
-    }));
+    if let Some(color) = get_color(layout_box, |style| &style.reset.border_bottom_color) {
+        list.push(DisplayCommand::SolidColor(color, Rect {
+            x: border_box.x,
+            y: border_box.y + border_box.height - d.border.bottom,
+            width: border_box.width,
+            height: d.border.bottom,
+        }));
+    }
 }
 
 impl Canvas {
@@ -154,3 +275,74 @@ pub fn print(list: DisplayList) {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use css::{self, Origin};
+    use html;
+    use layout::{self, Dimensions};
+    use media::{Device, MediaType};
+    use style;
+
+    // The order `source` styled by `css` is painted in, by the red component of each box's
+    // `background-color`.
+    fn paint_order(source: &str, css: &str) -> Vec<u8> {
+        let root = html::parse(String::from(source));
+        let css = format!("* {{ display: block; }} p {{ height: 10px; }} {}", css);
+        let sheets = [css::parse(css, Origin::Author)];
+        let mut viewport: Dimensions = Default::default();
+        viewport.content.width = 800.0;
+        viewport.content.height = 600.0;
+        let device = Device::new(MediaType::Screen, viewport);
+        let styled = style::style_tree(&root, &sheets, &device);
+        let layout_root = layout::layout_tree(&styled, viewport);
+        build_display_list(&layout_root).iter()
+            .map(|command| match *command { DisplayCommand::SolidColor(color, _) => color.r })
+            .collect()
+    }
+
+    #[test]
+    fn negative_z_index_paints_under_blocks() {
+        let order = paint_order("<div><p id=\"b\"></p><p id=\"n\"></p></div>",
+                                "div { background-color: #010000; } \
+                                 #b { background-color: #020000; } \
+                                 #n { position: relative; z-index: -1; \
+                                      background-color: #030000; }");
+        assert_eq!(order, [1, 3, 2]);
+    }
+
+    #[test]
+    fn floats_paint_after_blocks() {
+        let order = paint_order("<div><p id=\"f\"><p id=\"c\"></p></p><p id=\"b\"></p></div>",
+                                "#f { float: left; width: 10px; background-color: #010000; } \
+                                 #c { background-color: #020000; } \
+                                 #b { background-color: #030000; }");
+        assert_eq!(order, [3, 1, 2]);
+    }
+
+    #[test]
+    fn opacity_and_transforms_form_stacking_contexts() {
+        let order = paint_order("<div><p id=\"o\"><p id=\"c\"></p></p><p id=\"t\"></p>\
+                                 <p id=\"f\"></p><p id=\"b\"></p></div>",
+                                "#o { opacity: 0.5; background-color: #010000; } \
+                                 #c { background-color: #020000; } \
+                                 #t { transform: rotate(10deg); background-color: #030000; } \
+                                 #f { float: left; width: 10px; background-color: #040000; } \
+                                 #b { background-color: #050000; }");
+        // Both paint at `z-index` 0 after the blocks and floats, `#o` with its contents.
+        assert_eq!(order, [5, 4, 1, 2, 3]);
+    }
+
+    #[test]
+    fn positive_z_index_paints_last() {
+        let order = paint_order("<div><p id=\"p\"></p><p id=\"q\"></p><p id=\"r\"></p>\
+                                 <p id=\"b\"></p></div>",
+                                "#p, #q, #r { position: relative; } \
+                                 #p { z-index: 2; background-color: #010000; } \
+                                 #q { z-index: 1; background-color: #020000; } \
+                                 #r { background-color: #030000; } \
+                                 #b { background-color: #040000; }");
+        assert_eq!(order, [4, 3, 2, 1]);
+    }
+}
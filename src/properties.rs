@@ -0,0 +1,855 @@
+// Per-property data used by the cascade: which properties inherit, what their initial values
+// are, and how shorthand properties expand into longhands.
+
+use css::{self, ContentItem, Declaration, Track, Value, Unit};
+use std::collections::HashMap;
+
+pub struct Property {
+    pub name: &'static str,
+    pub inherited: bool,
+    initial: &'static str, // initial value, in CSS syntax
+}
+
+// Generate the table of longhand properties from a list of `field: "name" = "initial value"`,
+// along with a struct for the computed values of each group. A field is `None` when the property
+// has no value, in which case users fall back to its initial value.
+macro_rules! longhands {
+    (inherited { $($i_field:ident: $i_name:tt = $i_initial:tt,)* }
+     reset { $($r_field:ident: $r_name:tt = $r_initial:tt,)* }) => {
+        static PROPERTIES: &'static [Property] = &[
+            $(Property { name: $i_name, inherited: true, initial: $i_initial },)*
+            $(Property { name: $r_name, inherited: false, initial: $r_initial },)*
+        ];
+
+        /// Computed values of the inherited properties. Elements that don't set any of them share
+        /// their parent's.
+        #[derive(Clone, PartialEq, Default)]
+        pub struct InheritedStyle {
+            $(pub $i_field: Option<Value>,)*
+            pub custom: HashMap<String, Value>, // custom properties, which always inherit
+        }
+
+        /// Computed values of the properties that don't inherit.
+        #[derive(Clone, PartialEq, Default)]
+        pub struct ResetStyle {
+            $(pub $r_field: Option<Value>,)*
+        }
+
+        impl InheritedStyle {
+            /// The value of an inherited or custom property by name.
+            pub fn get(&self, name: &str) -> Option<&Value> {
+                match name {
+                    $($i_name => self.$i_field.as_ref(),)*
+                    _ => self.custom.get(name)
+                }
+            }
+
+            /// Set a property by name, returning false if it isn't an inherited or custom one.
+            pub fn set(&mut self, name: &str, value: Value) -> bool {
+                match name {
+                    $($i_name => self.$i_field = Some(value),)*
+                    _ if name.starts_with("--") => { self.custom.insert(name.to_string(), value); }
+                    _ => return false
+                }
+                true
+            }
+
+            /// The properties that have a value, in table order and then custom ones.
+            pub fn values(&self) -> Vec<(&str, &Value)> {
+                let mut values = Vec::new();
+                $(if let Some(ref value) = self.$i_field { values.push(($i_name, value)); })*
+                values.extend(self.custom.iter().map(|(name, value)| (&**name, value)));
+                values
+            }
+        }
+
+        impl ResetStyle {
+            /// The value of a non-inherited property by name.
+            pub fn get(&self, name: &str) -> Option<&Value> {
+                match name {
+                    $($r_name => self.$r_field.as_ref(),)*
+                    _ => None
+                }
+            }
+
+            /// Set a property by name, returning false if it isn't a non-inherited one.
+            pub fn set(&mut self, name: &str, value: Value) -> bool {
+                match name {
+                    $($r_name => self.$r_field = Some(value),)*
+                    _ => return false
+                }
+                true
+            }
+
+            /// The properties that have a value, in table order.
+            pub fn values(&self) -> Vec<(&str, &Value)> {
+                let mut values = Vec::new();
+                $(if let Some(ref value) = self.$r_field { values.push(($r_name, value)); })*
+                values
+            }
+        }
+    }
+}
+
+// https://www.w3.org/TR/CSS2/propidx.html
+longhands! {
+    inherited {
+        border_collapse:     "border-collapse"     = "separate",
+        border_spacing:      "border-spacing"      = "0px",
+        color:               "color"               = "#000000",
+        font_family:         "font-family"         = "serif",
+        font_size:           "font-size"           = "16px",
+        font_style:          "font-style"          = "normal",
+        font_variant:        "font-variant"        = "normal",
+        font_weight:         "font-weight"         = "normal",
+        line_height:         "line-height"         = "normal",
+        text_align:          "text-align"          = "left",
+        visibility:          "visibility"          = "visible",
+        white_space:         "white-space"         = "normal",
+    }
+    reset {
+        align_content:       "align-content"       = "normal",
+        align_items:         "align-items"         = "normal",
+        align_self:          "align-self"          = "auto",
+        background_color:    "background-color"    = "transparent",
+        border_top_color:    "border-top-color"    = "currentcolor",
+        border_right_color:  "border-right-color"  = "currentcolor",
+        border_bottom_color: "border-bottom-color" = "currentcolor",
+        border_left_color:   "border-left-color"   = "currentcolor",
+        border_top_style:    "border-top-style"    = "none",
+        border_right_style:  "border-right-style"  = "none",
+        border_bottom_style: "border-bottom-style" = "none",
+        border_left_style:   "border-left-style"   = "none",
+        border_top_width:    "border-top-width"    = "0px",
+        border_right_width:  "border-right-width"  = "0px",
+        border_bottom_width: "border-bottom-width" = "0px",
+        border_left_width:   "border-left-width"   = "0px",
+        bottom:              "bottom"              = "auto",
+        box_sizing:          "box-sizing"          = "content-box",
+        clear:               "clear"               = "none",
+        column_gap:          "column-gap"          = "normal",
+        content:             "content"             = "normal",
+        counter_increment:   "counter-increment"   = "none",
+        counter_reset:       "counter-reset"       = "none",
+        display:             "display"             = "inline",
+        flex_basis:          "flex-basis"          = "auto",
+        flex_direction:      "flex-direction"      = "row",
+        flex_grow:           "flex-grow"           = "0",
+        flex_shrink:         "flex-shrink"         = "1",
+        flex_wrap:           "flex-wrap"           = "nowrap",
+        float:               "float"               = "none",
+        grid_auto_columns:   "grid-auto-columns"   = "auto",
+        grid_auto_flow:      "grid-auto-flow"      = "row",
+        grid_auto_rows:      "grid-auto-rows"      = "auto",
+        grid_column_end:     "grid-column-end"     = "auto",
+        grid_column_start:   "grid-column-start"   = "auto",
+        grid_row_end:        "grid-row-end"        = "auto",
+        grid_row_start:      "grid-row-start"      = "auto",
+        grid_template_areas: "grid-template-areas" = "none",
+        grid_template_columns: "grid-template-columns" = "none",
+        grid_template_rows:  "grid-template-rows"  = "none",
+        height:              "height"              = "auto",
+        justify_content:     "justify-content"     = "normal",
+        justify_items:       "justify-items"       = "normal",
+        justify_self:        "justify-self"        = "auto",
+        left:                "left"                = "auto",
+        margin_top:          "margin-top"          = "0px",
+        margin_right:        "margin-right"        = "0px",
+        margin_bottom:       "margin-bottom"       = "0px",
+        margin_left:         "margin-left"         = "0px",
+        max_height:          "max-height"          = "none",
+        max_width:           "max-width"           = "none",
+        min_height:          "min-height"          = "0px",
+        min_width:           "min-width"           = "0px",
+        opacity:             "opacity"             = "1",
+        order:               "order"               = "0",
+        overflow:            "overflow"            = "visible",
+        padding_top:         "padding-top"         = "0px",
+        padding_right:       "padding-right"       = "0px",
+        padding_bottom:      "padding-bottom"      = "0px",
+        padding_left:        "padding-left"        = "0px",
+        position:            "position"            = "static",
+        right:               "right"               = "auto",
+        row_gap:             "row-gap"             = "normal",
+        table_layout:        "table-layout"        = "auto",
+        top:                 "top"                 = "auto",
+        transform:           "transform"           = "none",
+        vertical_align:      "vertical-align"      = "baseline",
+        width:               "width"               = "auto",
+        z_index:             "z-index"             = "auto",
+    }
+}
+
+/// Look up a property by name, or `None` if it isn't supported.
+pub fn lookup(name: &str) -> Option<&'static Property> {
+    PROPERTIES.iter().find(|property| property.name == name)
+}
+
+/// Whether the property inherits from the parent when it isn't specified.
+///
+/// Custom properties like `--brand-color` always inherit.
+pub fn is_inherited(name: &str) -> bool {
+    name.starts_with("--") || lookup(name).is_some_and(|property| property.inherited)
+}
+
+/// The initial value of a property, or `None` if it isn't supported.
+pub fn initial_value(name: &str) -> Option<Value> {
+    lookup(name).and_then(|property| css::parse_value(String::from(property.initial)))
+}
+
+// The longhands set by each shorthand property.
+static SHORTHANDS: &[(&str, &[&str])] = &[
+    ("margin", &["margin-top", "margin-right", "margin-bottom", "margin-left"]),
+    ("padding", &["padding-top", "padding-right", "padding-bottom", "padding-left"]),
+    ("inset", &["top", "right", "bottom", "left"]),
+    ("border-width", &["border-top-width", "border-right-width", "border-bottom-width",
+                       "border-left-width"]),
+    ("border-color", &["border-top-color", "border-right-color", "border-bottom-color",
+                       "border-left-color"]),
+    ("border-style", &["border-top-style", "border-right-style", "border-bottom-style",
+                       "border-left-style"]),
+    ("border", &["border-top-width", "border-right-width", "border-bottom-width",
+                 "border-left-width", "border-top-style", "border-right-style",
+                 "border-bottom-style", "border-left-style", "border-top-color",
+                 "border-right-color", "border-bottom-color", "border-left-color"]),
+    ("background", &["background-color"]),
+    ("font", &["font-style", "font-variant", "font-weight", "font-size", "line-height",
+               "font-family"]),
+    ("flex", &["flex-grow", "flex-shrink", "flex-basis"]),
+    ("flex-flow", &["flex-direction", "flex-wrap"]),
+    ("gap", &["row-gap", "column-gap"]),
+    ("grid-row", &["grid-row-start", "grid-row-end"]),
+    ("grid-column", &["grid-column-start", "grid-column-end"]),
+    ("grid-area", &["grid-row-start", "grid-column-start", "grid-row-end", "grid-column-end"]),
+];
+
+/// The longhands set by a shorthand property, or `None` if `name` isn't a shorthand.
+pub fn longhands(name: &str) -> Option<&'static [&'static str]> {
+    SHORTHANDS.iter()
+        .find(|&&(shorthand, _)| shorthand == name)
+        .map(|&(_, longhands)| longhands)
+}
+
+const BORDER_STYLES: &[&str] = &[
+    "none", "hidden", "dotted", "dashed", "solid", "double", "groove", "ridge", "inset", "outset"
+];
+
+const BORDER_WIDTHS: &[&str] = &["thin", "medium", "thick"];
+
+// The only color keywords supported; there are no named colors.
+const COLOR_KEYWORDS: &[&str] = &["currentcolor", "transparent"];
+
+// The keywords of a `background` layer, other than its color.
+const BACKGROUND_KEYWORDS: &[&str] = &[
+    "none", "repeat", "repeat-x", "repeat-y", "no-repeat", "space", "round", "scroll", "fixed",
+    "local", "left", "right", "top", "bottom", "center", "border-box", "padding-box",
+    "content-box", "auto", "cover", "contain", "/"
+];
+
+/// Turn the component values of a declaration into longhand declarations.
+///
+/// Shorthands expand into every longhand they set, with omitted parts reset to their initial
+/// values. Other properties become a single declaration. Invalid declarations are dropped as a
+/// whole: a shorthand with any part its grammar doesn't allow sets none of its longhands, and a
+/// longhand given more values than it takes isn't set.
+pub fn expand(name: &str, values: Vec<Value>, important: bool) -> Vec<Declaration> {
+    let declaration = |name: &str, value: Value| Declaration {
+        name: String::from(name),
+        value,
+        important,
+    };
+
+    if values.is_empty() || values.iter().any(|value| !value.is_valid()) {
+        return Vec::new();
+    }
+
+    let longhands = match longhands(name) {
+        Some(longhands) => longhands,
+        None => {
+            // A few longhands take a list.
+            let value = if name == "font-family" {
+                Some(Value::Keyword(join_family(&values)))
+            } else if name == "content" {
+                expand_content(values)
+            } else if name == "counter-reset" || name == "counter-increment" {
+                // An omitted integer is 0 for a reset and 1 for an increment.
+                expand_counters(values, if name == "counter-reset" { 0 } else { 1 })
+            } else if name.starts_with("grid-template-") && name != "grid-template-areas" ||
+                      name.starts_with("grid-auto-") && name != "grid-auto-flow" {
+                expand_tracks(values)
+            } else if name == "grid-template-areas" {
+                expand_areas(values)
+            } else if name == "grid-auto-flow" {
+                expand_auto_flow(values)
+            } else if name.starts_with("grid-row-") || name.starts_with("grid-column-") {
+                expand_grid_line(&values)
+            } else if name == "border-spacing" && values.len() == 2 {
+                // The horizontal spacing, then the vertical.
+                let mut values = values.into_iter().map(Box::new);
+                Some(Value::Pair(values.next().unwrap(), values.next().unwrap()))
+            } else if name == "transform" && values.len() > 1 {
+                // Each function of a transform list parses as a value of its own.
+                let mut functions = Vec::new();
+                for value in values {
+                    match value {
+                        Value::Transform(function) => functions.extend(function),
+                        _ => return Vec::new()
+                    }
+                }
+                Some(Value::Transform(functions))
+            } else if values.len() == 1 {
+                values.into_iter().next()
+            } else {
+                // Every other longhand takes a single value.
+                None
+            };
+            return value.map(|value| declaration(name, value)).into_iter().collect();
+        }
+    };
+
+    // The CSS-wide keywords apply to every longhand.
+    if let [Value::Keyword(ref keyword)] = values[..] {
+        if keyword == "inherit" || keyword == "initial" || keyword == "unset" {
+            return longhands.iter()
+                .map(|longhand| declaration(longhand, values[0].clone()))
+                .collect();
+        }
+    }
+
+    let expanded = match name {
+        "margin" | "inset" => expand_sides(values, |value| is_length(value) || is_auto(value)),
+        "padding" => expand_sides(values, is_length),
+        "border-width" => expand_sides(values, is_border_width),
+        "border-style" => expand_sides(values, is_border_style),
+        "border-color" => expand_sides(values, is_color),
+        "border" => expand_border(values),
+        "background" => expand_background(values),
+        "font" => expand_font(values),
+        "flex" => expand_flex(values),
+        "flex-flow" => expand_flex_flow(values),
+        "gap" => expand_gap(values),
+        "grid-row" | "grid-column" | "grid-area" => expand_grid_placement(values, longhands.len()),
+        _ => None
+    };
+
+    match expanded {
+        Some(expanded) => longhands.iter().zip(expanded)
+            .map(|(longhand, value)| {
+                let value = value.or_else(|| initial_value(longhand))
+                                 .unwrap_or(Value::Keyword(String::from("initial")));
+                declaration(longhand, value)
+            })
+            .collect(),
+        None => Vec::new()
+    }
+}
+
+// Expand one to four values, each of which must be `valid`, into top, right, bottom and left.
+fn expand_sides(values: Vec<Value>, valid: fn(&Value) -> bool)
+                -> Option<Vec<Option<Value>>> {
+    if !values.iter().all(valid) {
+        return None;
+    }
+    let (top, right, bottom, left) = match values.len() {
+        1 => (0, 0, 0, 0),
+        2 => (0, 1, 0, 1),
+        3 => (0, 1, 2, 1),
+        4 => (0, 1, 2, 3),
+        _ => return None
+    };
+    Some([top, right, bottom, left].iter().map(|&i| Some(values[i].clone())).collect())
+}
+
+// `border: <width> || <style> || <color>`, applied to all four sides.
+fn expand_border(values: Vec<Value>) -> Option<Vec<Option<Value>>> {
+    let (mut width, mut style, mut color) = (None, None, None);
+    for value in values {
+        if width.is_none() && is_border_width(&value) {
+            width = Some(value);
+        } else if style.is_none() && is_border_style(&value) {
+            style = Some(value);
+        } else if color.is_none() && is_color(&value) {
+            color = Some(value);
+        } else {
+            return None;
+        }
+    }
+    let mut expanded = vec![width; 4];
+    expanded.extend(vec![style; 4]);
+    expanded.extend(vec![color; 4]);
+    Some(expanded)
+}
+
+// `background: [<bg-layer> ,]* <final-bg-layer>`, of which only the color is supported. The
+// images, positions, sizes and boxes of the layers are checked, and then ignored. Images other
+// than `none` don't parse.
+fn expand_background(values: Vec<Value>) -> Option<Vec<Option<Value>>> {
+    let mut color = None;
+    for value in values {
+        match value {
+            // Only the final layer has a color.
+            Value::Keyword(ref k) if k == "," && color.is_none() => {}
+            Value::Keyword(ref k) if BACKGROUND_KEYWORDS.contains(&&**k) => {}
+            _ if is_length(&value) => {}
+            _ if color.is_none() && is_color(&value) => color = Some(value),
+            _ => return None
+        }
+    }
+    Some(vec![color])
+}
+
+// `font: [<style> || <variant> || <weight>]? <size> [/ <line-height>]? <family>`
+fn expand_font(values: Vec<Value>) -> Option<Vec<Option<Value>>> {
+    let (mut style, mut variant, mut weight) = (None, None, None);
+    let mut values = values.into_iter().peekable();
+
+    // Everything before the size is a style, variant or weight keyword.
+    loop {
+        match values.peek() {
+            Some(&Value::Length(..)) | None => break,
+            Some(&Value::Number(_)) if weight.is_none() => weight = values.next(),
+            Some(Value::Keyword(k)) => match &**k {
+                "normal" => { values.next(); }
+                "italic" | "oblique" if style.is_none() => style = values.next(),
+                "small-caps" if variant.is_none() => variant = values.next(),
+                "bold" | "bolder" | "lighter" if weight.is_none() => weight = values.next(),
+                _ => return None
+            },
+            _ => return None
+        }
+    }
+
+    let size = values.next();
+    let mut line_height = None;
+    if values.peek() == Some(&Value::Keyword(String::from("/"))) {
+        values.next();
+        line_height = values.next();
+    }
+    let family: Vec<Value> = values.collect();
+    if size.is_none() || family.is_empty() {
+        return None;
+    }
+    Some(vec![style, variant, weight, size, line_height,
+              Some(Value::Keyword(join_family(&family)))])
+}
+
+// `content: normal | none | [<string> | attr() | counter() | counters()]+`
+fn expand_content(values: Vec<Value>) -> Option<Value> {
+    if let [Value::Keyword(_)] = values[..] {
+        return values.into_iter().next();
+    }
+    let mut items = Vec::new();
+    for value in values {
+        match value {
+            Value::Str(text) => items.push(ContentItem::Text(text)),
+            Value::Content(parts) => items.extend(parts),
+            _ => return None
+        }
+    }
+    Some(Value::Content(items))
+}
+
+// `counter-reset` and `counter-increment`: `none | [<name> <integer>?]+`
+fn expand_counters(values: Vec<Value>, default: i32) -> Option<Value> {
+    if let [Value::Keyword(ref k)] = values[..] {
+        if k == "none" || k == "inherit" || k == "initial" || k == "unset" {
+            return Some(values[0].clone());
+        }
+    }
+    let mut counters: Vec<(String, i32)> = Vec::new();
+    for value in values {
+        match value {
+            Value::Keyword(name) => counters.push((name, default)),
+            Value::Number(n) if n.fract() == 0.0 => match counters.last_mut() {
+                Some(counter) => counter.1 = n as i32,
+                None => return None
+            },
+            _ => return None
+        }
+    }
+    Some(Value::Counters(counters))
+}
+
+// `flex: none | [<grow> <shrink>?] || <basis>`
+fn expand_flex(values: Vec<Value>) -> Option<Vec<Option<Value>>> {
+    let keyword = |k: &str| Value::Keyword(String::from(k));
+    if let [Value::Keyword(ref k)] = values[..] {
+        match &**k {
+            "none" => return Some(vec![Some(Value::Number(0.0)), Some(Value::Number(0.0)),
+                                       Some(keyword("auto"))]),
+            "auto" => return Some(vec![Some(Value::Number(1.0)), Some(Value::Number(1.0)),
+                                       Some(keyword("auto"))]),
+            _ => {}
+        }
+    }
+
+    let numbers: Vec<f32> = values.iter().filter_map(|value| match *value {
+        Value::Number(n) => Some(n),
+        _ => None
+    }).collect();
+    // Besides the numbers there can be one basis.
+    let mut bases = values.iter().filter(|value| !matches!(**value, Value::Number(_)));
+    let basis = bases.next().cloned();
+    if bases.next().is_some() || basis.as_ref().is_some_and(|basis| !is_flex_basis(basis)) {
+        return None;
+    }
+
+    // With the grow factor given, an omitted basis is 0 rather than the initial `auto`.
+    let (grow, shrink, basis) = match numbers.len() {
+        0 => (1.0, 1.0, basis),
+        1 => (numbers[0], 1.0, basis.or(Some(Value::Length(0.0, Unit::Percent)))),
+        2 => (numbers[0], numbers[1], basis.or(Some(Value::Length(0.0, Unit::Percent)))),
+        // A third unitless number can only be a zero basis.
+        3 if numbers[2] == 0.0 => (numbers[0], numbers[1], Some(Value::Length(0.0, Unit::Px))),
+        _ => return None
+    };
+    Some(vec![Some(Value::Number(grow)), Some(Value::Number(shrink)), basis])
+}
+
+// `flex-flow: <direction> || <wrap>`
+fn expand_flex_flow(values: Vec<Value>) -> Option<Vec<Option<Value>>> {
+    let (mut direction, mut wrap) = (None, None);
+    for value in values {
+        match value {
+            Value::Keyword(ref k) if direction.is_none() &&
+                    ["row", "row-reverse", "column", "column-reverse"].contains(&&**k) => {
+                direction = Some(value.clone())
+            }
+            Value::Keyword(ref k) if wrap.is_none() &&
+                    ["nowrap", "wrap", "wrap-reverse"].contains(&&**k) => {
+                wrap = Some(value.clone())
+            }
+            _ => return None
+        }
+    }
+    Some(vec![direction, wrap])
+}
+
+// `gap: <row-gap> <column-gap>?`
+fn expand_gap(values: Vec<Value>) -> Option<Vec<Option<Value>>> {
+    let is_gap = |value: &Value| {
+        is_length(value) || *value == Value::Keyword(String::from("normal"))
+    };
+    if !values.iter().all(is_gap) {
+        return None;
+    }
+    match values.len() {
+        1 => Some(vec![Some(values[0].clone()), Some(values[0].clone())]),
+        2 => Some(values.into_iter().map(Some).collect()),
+        _ => None
+    }
+}
+
+// `grid-template-columns` and friends: `none | [<line-names>? <track-size>]+ <line-names>?`,
+// where a track size may be a `minmax()` and the list may hold `repeat()`s.
+fn expand_tracks(values: Vec<Value>) -> Option<Value> {
+    if let [Value::Keyword(ref k)] = values[..] {
+        if k == "none" || k == "inherit" || k == "initial" || k == "unset" {
+            return Some(values[0].clone());
+        }
+    }
+    let mut tracks = Vec::new();
+    for value in values {
+        match value {
+            Value::Tracks(parts) => tracks.extend(parts),
+            Value::Length(..) | Value::Calc(_) => tracks.push(Track::Size(value.clone(), value)),
+            Value::Keyword(ref k) if k == "auto" || k == "min-content" || k == "max-content" => {
+                tracks.push(Track::Size(value.clone(), value.clone()))
+            }
+            _ => return None
+        }
+    }
+    Some(Value::Tracks(tracks))
+}
+
+// `grid-template-areas: none | <string>+`, with one string per row. Every row must have the
+// same number of cells.
+fn expand_areas(values: Vec<Value>) -> Option<Value> {
+    if let [Value::Keyword(_)] = values[..] {
+        return values.into_iter().next();
+    }
+    let mut rows: Vec<Vec<String>> = Vec::new();
+    for value in values {
+        match value {
+            Value::Str(ref row) => {
+                rows.push(row.split_whitespace().map(String::from).collect())
+            }
+            _ => return None
+        }
+    }
+    if rows.iter().any(|row| row.is_empty() || row.len() != rows[0].len()) {
+        return None;
+    }
+    Some(Value::Areas(rows))
+}
+
+// `grid-auto-flow: [row | column] || dense`, kept as a single keyword like `column dense`.
+fn expand_auto_flow(values: Vec<Value>) -> Option<Value> {
+    let mut keywords = Vec::new();
+    for value in values {
+        match value {
+            Value::Keyword(k) => keywords.push(k),
+            _ => return None
+        }
+    }
+    match keywords.len() {
+        1 | 2 => Some(Value::Keyword(keywords.join(" "))),
+        _ => None
+    }
+}
+
+// `grid-row-start` and friends: `auto | <integer> | <name> | span <integer>`
+fn expand_grid_line(values: &[Value]) -> Option<Value> {
+    match *values {
+        [ref value @ Value::Keyword(_)] => Some(value.clone()),
+        [Value::Number(n)] if n.fract() == 0.0 && n != 0.0 => Some(values[0].clone()),
+        [Value::Keyword(ref k), Value::Number(n)] | [Value::Number(n), Value::Keyword(ref k)]
+            if k == "span" && n.fract() == 0.0 && n >= 1.0 => Some(Value::Span(n as u32)),
+        _ => None
+    }
+}
+
+// `grid-row`, `grid-column` and `grid-area`: grid lines separated by `/`. An omitted line is
+// the same as the first one if that one is a name, and `auto` otherwise.
+fn expand_grid_placement(values: Vec<Value>, count: usize) -> Option<Vec<Option<Value>>> {
+    let mut lines = Vec::new();
+    for part in values.split(|value| *value == Value::Keyword(String::from("/"))) {
+        lines.push(expand_grid_line(part)?);
+    }
+    if lines.len() > count {
+        return None;
+    }
+    while lines.len() < count {
+        // An end line copies the start on the same axis, and the column start of `grid-area`
+        // copies the row start.
+        let same = if lines.len() >= 2 { lines.len() - 2 } else { 0 };
+        let line = match lines[same] {
+            Value::Keyword(ref k) if k != "auto" && k != "span" => Value::Keyword(k.clone()),
+            _ => Value::Keyword(String::from("auto"))
+        };
+        lines.push(line);
+    }
+    Some(lines.into_iter().map(Some).collect())
+}
+
+// A length, percentage or math expression, or a unitless zero.
+fn is_length(value: &Value) -> bool {
+    match *value {
+        Value::Length(_, ref unit) => *unit != Unit::Fr,
+        Value::Number(n) => n == 0.0,
+        Value::Calc(_) => true,
+        _ => false
+    }
+}
+
+fn is_auto(value: &Value) -> bool {
+    *value == Value::Keyword(String::from("auto"))
+}
+
+fn is_flex_basis(value: &Value) -> bool {
+    match *value {
+        Value::Keyword(ref k) => k == "auto" || k == "content",
+        _ => is_length(value)
+    }
+}
+
+fn is_border_width(value: &Value) -> bool {
+    match *value {
+        Value::Keyword(ref k) => BORDER_WIDTHS.contains(&&**k),
+        Value::Length(_, Unit::Percent) => false,
+        _ => is_length(value)
+    }
+}
+
+fn is_border_style(value: &Value) -> bool {
+    match *value {
+        Value::Keyword(ref k) => BORDER_STYLES.contains(&&**k),
+        _ => false
+    }
+}
+
+fn is_color(value: &Value) -> bool {
+    match *value {
+        Value::ColorValue(_) => true,
+        Value::Keyword(ref k) => COLOR_KEYWORDS.contains(&&**k),
+        _ => false
+    }
+}
+
+// Join the names in a font family list, like `"Helvetica Neue", Arial, sans-serif`.
+fn join_family(values: &[Value]) -> String {
+    let mut family = String::new();
+    for value in values {
+        match *value {
+            Value::Keyword(ref k) if k == "," => family.push_str(", "),
+            Value::Keyword(ref k) | Value::Str(ref k) => {
+                if !family.is_empty() && !family.ends_with(' ') {
+                    family.push(' ');
+                }
+                family.push_str(k);
+            }
+            _ => {}
+        }
+    }
+    family
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn value(source: &str) -> Value {
+        css::parse_value(String::from(source)).unwrap()
+    }
+
+    // The declarations that `name: source` expands into, as pairs of names and values.
+    fn expanded(name: &str, source: &str) -> Vec<(String, Value)> {
+        let values = css::parse_values(String::from(source)).unwrap();
+        expand(name, values, false).into_iter()
+            .map(|declaration| (declaration.name, declaration.value))
+            .collect()
+    }
+
+    // Check that `name: source` sets every longhand of the shorthand `name`, in order, to the
+    // values of `expected`.
+    fn assert_expands(name: &str, source: &str, expected: &[Value]) {
+        let declarations = expanded(name, source);
+        let longhands = longhands(name).unwrap();
+        assert_eq!(declarations.len(), longhands.len(), "{}: {}", name, source);
+        for (i, (longhand, value)) in declarations.into_iter().enumerate() {
+            assert_eq!(longhand, longhands[i]);
+            assert!(value == expected[i], "{}: {} sets {} wrongly", name, source, longhand);
+        }
+    }
+
+    fn assert_invalid(name: &str, source: &str) {
+        assert!(expanded(name, source).is_empty(), "{}: {} should be invalid", name, source);
+    }
+
+    #[test]
+    fn sides() {
+        let examples = [("margin", ["1px", "2px", "3px", "auto"]),
+                        ("padding", ["1px", "2px", "3px", "4%"]),
+                        ("inset", ["1px", "auto", "3px", "4px"]),
+                        ("border-width", ["thin", "2px", "3px", "thick"]),
+                        ("border-style", ["solid", "dashed", "none", "double"]),
+                        ("border-color", ["#ff0000", "#00ff00", "currentcolor", "transparent"])];
+        // Which of the given values each side takes, by the number of values.
+        let sides = [[0, 0, 0, 0], [0, 1, 0, 1], [0, 1, 2, 1], [0, 1, 2, 3]];
+        for &(name, ref values) in &examples {
+            for count in 1..5 {
+                let source = values[..count].join(" ");
+                let expected: Vec<Value> =
+                    sides[count - 1].iter().map(|&i| value(values[i])).collect();
+                assert_expands(name, &source, &expected);
+            }
+            assert_invalid(name, &format!("{} {}", values.join(" "), values[0]));
+        }
+        assert_invalid("padding", "auto");
+        assert_invalid("margin", "solid");
+        assert_invalid("border-width", "10%");
+        assert_invalid("border-style", "1px");
+        assert_invalid("border-color", "solid");
+        assert_invalid("border-color", "foo");
+    }
+
+    #[test]
+    fn border() {
+        let border = |width, style, color| {
+            let mut values = vec![value(width); 4];
+            values.extend(vec![value(style); 4]);
+            values.extend(vec![value(color); 4]);
+            values
+        };
+        assert_expands("border", "1px", &border("1px", "none", "currentcolor"));
+        assert_expands("border", "dashed", &border("0px", "dashed", "currentcolor"));
+        assert_expands("border", "1px solid", &border("1px", "solid", "currentcolor"));
+        assert_expands("border", "#ff0000 thick double", &border("thick", "double", "#ff0000"));
+        assert_expands("border", "inherit", &vec![value("inherit"); 12]);
+        assert_invalid("border", "1px solid foo");
+        assert_invalid("border", "1px 2px");
+        assert_invalid("border", "solid dashed");
+        assert_invalid("border", "1px solid #ff0000 #00ff00");
+    }
+
+    #[test]
+    fn background() {
+        assert_expands("background", "#ff0000", &[value("#ff0000")]);
+        assert_expands("background", "none no-repeat center / cover transparent",
+                       &[value("transparent")]);
+        assert_expands("background", "none, top 10px right #ff0000", &[value("#ff0000")]);
+        assert_expands("background", "repeat-x", &[value("transparent")]);
+        assert_invalid("background", "foo");
+        assert_invalid("background", "#ff0000 #00ff00");
+        assert_invalid("background", "#ff0000, none");
+    }
+
+    #[test]
+    fn font() {
+        let family = |names: &str| Value::Keyword(String::from(names));
+        assert_expands("font", "12px serif",
+                       &[value("normal"), value("normal"), value("normal"), value("12px"),
+                         value("normal"), family("serif")]);
+        assert_expands("font", "italic bold 12px / 1.5 \"Helvetica Neue\", sans-serif",
+                       &[value("italic"), value("normal"), value("bold"), value("12px"),
+                         value("1.5"), family("Helvetica Neue, sans-serif")]);
+        assert_expands("font", "small-caps 600 2em monospace",
+                       &[value("normal"), value("small-caps"), value("600"), value("2em"),
+                         value("normal"), family("monospace")]);
+        assert_invalid("font", "12px");
+        assert_invalid("font", "bold bold 12px serif");
+    }
+
+    #[test]
+    fn flex() {
+        let flex = |grow, shrink, basis| [value(grow), value(shrink), value(basis)];
+        assert_expands("flex", "none", &flex("0", "0", "auto"));
+        assert_expands("flex", "auto", &flex("1", "1", "auto"));
+        assert_expands("flex", "2", &flex("2", "1", "0%"));
+        assert_expands("flex", "2 3", &flex("2", "3", "0%"));
+        assert_expands("flex", "2 3 10px", &flex("2", "3", "10px"));
+        assert_expands("flex", "10px 2", &flex("2", "1", "10px"));
+        assert_expands("flex", "content", &flex("1", "1", "content"));
+        assert_expands("flex", "2 0 0", &flex("2", "0", "0px"));
+        assert_invalid("flex", "1px 2px");
+        assert_invalid("flex", "foo");
+        assert_invalid("flex", "1 2 3 4");
+    }
+
+    #[test]
+    fn flex_flow_and_gap() {
+        assert_expands("flex-flow", "column", &[value("column"), value("nowrap")]);
+        assert_expands("flex-flow", "wrap", &[value("row"), value("wrap")]);
+        assert_expands("flex-flow", "wrap-reverse row-reverse",
+                       &[value("row-reverse"), value("wrap-reverse")]);
+        assert_invalid("flex-flow", "row column");
+        assert_invalid("flex-flow", "rows");
+        assert_expands("gap", "1px", &[value("1px"), value("1px")]);
+        assert_expands("gap", "normal 2%", &[value("normal"), value("2%")]);
+        assert_invalid("gap", "1px 2px 3px");
+        assert_invalid("gap", "auto");
+    }
+
+    #[test]
+    fn grid_placement() {
+        let lines = |lines: &[&str]| lines.iter().map(|line| value(line)).collect::<Vec<_>>();
+        assert_expands("grid-area", "a", &lines(&["a", "a", "a", "a"]));
+        assert_expands("grid-area", "1", &lines(&["1", "auto", "auto", "auto"]));
+        assert_expands("grid-area", "1 / a", &lines(&["1", "a", "auto", "a"]));
+        assert_expands("grid-area", "1 / 2 / 3", &lines(&["1", "2", "3", "auto"]));
+        assert_expands("grid-area", "1 / 2 / 3 / 4", &lines(&["1", "2", "3", "4"]));
+        assert_expands("grid-row", "2 / span 3", &[value("2"), Value::Span(3)]);
+        assert_invalid("grid-area", "1 / 2 / 3 / 4 / 5");
+        assert_invalid("grid-column", "0");
+    }
+
+    #[test]
+    fn longhands_take_one_value() {
+        assert!(expanded("width", "1px") == [(String::from("width"), value("1px"))]);
+        assert!(expanded("width", "1px 2px").is_empty());
+        assert!(expanded("margin-top", "").is_empty());
+        assert!(expanded("border-spacing", "1px 2px")
+                == [(String::from("border-spacing"),
+                     Value::Pair(Box::new(value("1px")), Box::new(value("2px"))))]);
+    }
+}
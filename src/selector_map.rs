@@ -0,0 +1,116 @@
+// Selectors bucketed by the id, class or tag name their rightmost compound selector requires,
+// so that matching an element only tests selectors that could possibly apply to it.
+
+use css::{Selector, Combinator, SimpleSelector, Specificity, PseudoElement};
+use dom::ElementData;
+use bloom::{self, AncestorFilter};
+use std::collections::HashMap;
+
+/// One selector of a rule, with what its ancestors must contain.
+pub struct SelectorEntry<'a> {
+    pub rule: usize, // index of the rule the selector belongs to
+    pub selector: &'a Selector,
+    pub specificity: Specificity,
+    pub pseudo_element: Option<PseudoElement>,
+    // Hashes of the tags, ids and classes some ancestor of a matching element must have.
+    ancestor_hashes: Vec<u32>,
+}
+
+pub struct SelectorMap<'a> {
+    ids: HashMap<&'a str, Vec<SelectorEntry<'a>>>,
+    classes: HashMap<&'a str, Vec<SelectorEntry<'a>>>,
+    tags: HashMap<&'a str, Vec<SelectorEntry<'a>>>,
+    universal: Vec<SelectorEntry<'a>>,
+}
+
+impl<'a> SelectorMap<'a> {
+    pub fn new() -> SelectorMap<'a> {
+        SelectorMap {
+            ids: HashMap::new(),
+            classes: HashMap::new(),
+            tags: HashMap::new(),
+            universal: Vec::new(),
+        }
+    }
+
+    /// Add a selector of the rule at index `rule`.
+    pub fn insert(&mut self, rule: usize, selector: &'a Selector) {
+        let mut ancestor_hashes = Vec::new();
+        collect_ancestor_hashes(selector, false, &mut ancestor_hashes);
+        let entry = SelectorEntry {
+            rule,
+            selector,
+            specificity: selector.specificity(),
+            pseudo_element: rightmost(selector).pseudo_element,
+            ancestor_hashes,
+        };
+
+        // The id is the most selective key, then a class, then the tag name.
+        let rightmost = rightmost(selector);
+        let bucket = if let Some(ref id) = rightmost.id {
+            self.ids.entry(id).or_default()
+        } else if let Some(class) = rightmost.class.first() {
+            self.classes.entry(class).or_default()
+        } else if let Some(ref tag) = rightmost.tag_name {
+            self.tags.entry(tag).or_default()
+        } else {
+            &mut self.universal
+        };
+        bucket.push(entry);
+    }
+
+    /// Call `f` for each selector that might match `elem`, skipping those whose required
+    /// ancestors are ruled out by `filter`. A selector is passed at most once.
+    pub fn for_each_candidate<F>(&self, elem: &ElementData, filter: &AncestorFilter, mut f: F)
+        where F: FnMut(&SelectorEntry<'a>)
+    {
+        let mut visit = |entries: Option<&Vec<SelectorEntry<'a>>>| {
+            for entry in entries.into_iter().flatten() {
+                if entry.ancestor_hashes.iter().all(|&hash| filter.might_contain(hash)) {
+                    f(entry);
+                }
+            }
+        };
+        if let Some(id) = elem.id() {
+            visit(self.ids.get(&**id));
+        }
+        for class in elem.classes() {
+            visit(self.classes.get(class));
+        }
+        visit(self.tags.get(&*elem.tag_name));
+        visit(Some(&self.universal));
+    }
+}
+
+impl<'a> Default for SelectorMap<'a> {
+    fn default() -> SelectorMap<'a> {
+        SelectorMap::new()
+    }
+}
+
+fn rightmost(selector: &Selector) -> &SimpleSelector {
+    match *selector {
+        Selector::Simple(ref simple) => simple,
+        Selector::Complex(_, _, ref simple) => simple,
+    }
+}
+
+// Collect hashes for the compound selectors that must match ancestors of the subject.
+//
+// In `a b + c`, `b` is a sibling of the subject and tells us nothing about the ancestors, but
+// `a` is an ancestor of `b` and therefore of the subject too.
+fn collect_ancestor_hashes(selector: &Selector, is_ancestor: bool, hashes: &mut Vec<u32>) {
+    let simple = rightmost(selector);
+    if is_ancestor {
+        hashes.extend(simple.tag_name.iter().map(|tag| bloom::tag_hash(tag)));
+        hashes.extend(simple.id.iter().map(|id| bloom::id_hash(id)));
+        hashes.extend(simple.class.iter().map(|class| bloom::class_hash(class)));
+    }
+    if let Selector::Complex(ref left, combinator, _) = *selector {
+        let left_is_ancestor = match combinator {
+            Combinator::Descendant | Combinator::Child => true,
+            Combinator::NextSibling | Combinator::SubsequentSibling => false,
+        };
+        collect_ancestor_hashes(left, left_is_ancestor, hashes);
+    }
+}
@@ -1,56 +1,211 @@
 use dom::{Node, NodeType, ElementData};
-use css::{Selector, Rule, Stylesheet, Value, SimpleSelector, Specificity};
+use css::{Selector, Combinator, Rule, Stylesheet, Value, Unit, SimpleSelector, Specificity};
+use css::{LengthContext, AttrSelector, AttrOperator, PseudoClass, PseudoElement, Origin};
+use media::Device;
+use bloom::AncestorFilter;
+use selector_map::SelectorMap;
+use properties::{self, InheritedStyle, ResetStyle};
+use variables;
+use css;
 use std::collections::{HashMap, VecDeque};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
 
-// Map from CSS property names to values.
+/// The font size used when no `font-size` is specified, in px.
+pub const DEFAULT_FONT_SIZE: f32 = 16.0;
+
+// Map from CSS property names to values, used while cascading.
 type PropertyMap = HashMap<String, Value>;
 
+/// The computed values of a node's properties.
+#[derive(Clone, PartialEq)]
+pub struct ComputedStyle {
+    pub inherited: Arc<InheritedStyle>,
+    pub reset: Arc<ResetStyle>,
+}
+
+impl ComputedStyle {
+    /// The value of a supported or custom property by name, for debugging and for the cascade.
+    fn get(&self, name: &str) -> Option<&Value> {
+        self.inherited.get(name).or_else(|| self.reset.get(name))
+    }
+
+    /// All properties that have a value.
+    pub fn values(&self) -> Vec<(&str, &Value)> {
+        let mut values = self.inherited.values();
+        values.extend(self.reset.values());
+        values
+    }
+}
+
 // A node with associated style data.
 #[derive(Clone)]
 pub struct StyledNode<'a> {
     pub node: &'a Node, // pointer to a DOM node
-    specified_values: PropertyMap,
+    computed: Arc<ComputedStyle>, // shared with other nodes of the same style
     pub children: Vec<StyledNode<'a>>,
+    pub pseudo: Option<PseudoElement>, // set if this styles a pseudo-element of `node`
+    pub before: Option<Box<StyledNode<'a>>>,
+    pub after: Option<Box<StyledNode<'a>>>,
+}
+
+// Two style trees are equal when they style the same nodes with equal values.
+impl<'a> PartialEq for StyledNode<'a> {
+    fn eq(&self, other: &StyledNode) -> bool {
+        ::std::ptr::eq(self.node, other.node) && self.computed == other.computed
+            && self.children == other.children && self.pseudo == other.pseudo
+            && self.before == other.before && self.after == other.after
+    }
 }
 
 pub enum Display {
     Horizontal,
     Vertical,
+    Flex,
+    Grid,
+    Table(TablePart),
     None
 }
 
+/// The kinds of box in the CSS table model.
+#[derive(Clone, Copy, PartialEq)]
+pub enum TablePart {
+    Table,
+    RowGroup, // including header and footer groups
+    Row,
+    Cell,
+    Caption,
+}
+
 impl<'a> StyledNode<'a> {
-    /// Return the specified value of a property if it exists, otherwise `None`.
+    /// The computed values of the node.
+    pub fn style(&self) -> &ComputedStyle {
+        &self.computed
+    }
+
+    /// Look up a computed value by property name, for debugging. Code that knows which property
+    /// it wants should use the fields of `style()` instead.
+    ///
+    /// Returns `None` for a property that isn't supported. Debug builds assert that it is, so a
+    /// misspelled name can't pass for an unset one in tests.
     pub fn value(&self, name: &str) -> Option<Value> {
-        self.specified_values.get(name).cloned()
+        debug_assert!(name.starts_with("--") || properties::lookup(name).is_some(),
+                      "Unsupported property {}", name);
+        self.computed.get(name).cloned()
     }
 
     /// The value of the `display` property (defaults to inline).
+    ///
+    /// The nonstandard `display: horizontal` lays out an element's children side by side.
+    /// Without inline layout, `inline-flex`, `inline-grid` and `inline-table` are laid out like
+    /// `flex`, `grid` and `table`.
     pub fn display(&self) -> Display {
-        match self.value("display") {
-            Some(Value::Keyword(s)) => match &*s {
+        match self.computed.reset.display {
+            Some(Value::Keyword(ref s)) => match &**s {
                 "none" => Display::None,
+                "horizontal" => Display::Horizontal,
+                "flex" | "inline-flex" => Display::Flex,
+                "grid" | "inline-grid" => Display::Grid,
+                "table" | "inline-table" => Display::Table(TablePart::Table),
+                "table-row-group" | "table-header-group" | "table-footer-group" => {
+                    Display::Table(TablePart::RowGroup)
+                }
+                "table-row" => Display::Table(TablePart::Row),
+                "table-cell" => Display::Table(TablePart::Cell),
+                "table-caption" => Display::Table(TablePart::Caption),
+                // Columns only style the cells in them, which isn't supported.
+                "table-column" | "table-column-group" => Display::None,
                 _ => Display::Vertical
             },
             _ => Display::Vertical
         }
     }
+}
+
+/// An element together with the parts of the tree that selector combinators can refer to.
+#[derive(Clone, Copy)]
+struct MatchingContext<'a, 'b> {
+    siblings: &'a [Node], // the element and its siblings, in document order
+    index: usize,         // position of the element within `siblings`
+    parent: Option<&'b MatchingContext<'a, 'b>>,
+}
+
+impl<'a, 'b> MatchingContext<'a, 'b> {
+    fn node(&self) -> &'a Node {
+        &self.siblings[self.index]
+    }
+
+    fn elem(&self) -> &'a ElementData {
+        match self.node().node_type {
+            NodeType::Element(ref elem) => elem,
+            NodeType::Text(_) => panic!("Text nodes can't be matched by selectors")
+        }
+    }
+
+    /// The element siblings that come before this element, nearest first.
+    fn preceding_siblings(&self) -> impl Iterator<Item = MatchingContext<'a, 'b>> {
+        let ctx = *self;
+        (0..ctx.index).rev()
+            .filter(move |&i| is_element(&ctx.siblings[i]))
+            .map(move |i| MatchingContext { index: i, ..ctx })
+    }
+
+    /// The element siblings that come after this element, nearest first.
+    fn following_siblings(&self) -> impl Iterator<Item = MatchingContext<'a, 'b>> {
+        let ctx = *self;
+        (ctx.index + 1..ctx.siblings.len())
+            .filter(move |&i| is_element(&ctx.siblings[i]))
+            .map(move |i| MatchingContext { index: i, ..ctx })
+    }
+
+    /// Whether `other` is an element with the same tag name as this one.
+    fn same_type(&self, other: &MatchingContext) -> bool {
+        self.elem().tag_name == other.elem().tag_name
+    }
 
-    /// Return the specified value of property `name`, or property `fallback_name` if that doesn't
-    /// exist. or value `default` if neither does.
-    pub fn lookup(&self, name: &str, fallback_name: &str, default: &Value) -> Value {
-        self.value(name).unwrap_or_else(|| self.value(fallback_name)
-                        .unwrap_or_else(|| default.clone()))
+    /// The ancestors of this element, nearest first.
+    fn ancestors(&self) -> impl Iterator<Item = &'b MatchingContext<'a, 'b>> {
+        let mut next = self.parent;
+        ::std::iter::from_fn(move || {
+            let current = next;
+            next = current.and_then(|ancestor| ancestor.parent);
+            current
+        })
     }
 }
 
-fn matches(elem: &ElementData, selector: &Selector) -> bool {
+fn is_element(node: &Node) -> bool {
+    match node.node_type {
+        NodeType::Element(_) => true,
+        NodeType::Text(_) => false
+    }
+}
+
+fn matches(ctx: &MatchingContext, selector: &Selector) -> bool {
     match *selector {
-        Selector::Simple(ref simple_selector) => matches_simple_selector(elem, simple_selector)
+        Selector::Simple(ref simple_selector) => matches_simple_selector(ctx, simple_selector),
+        Selector::Complex(ref left, combinator, ref right) => {
+            if !matches_simple_selector(ctx, right) {
+                return false;
+            }
+            match combinator {
+                Combinator::Child => ctx.parent.is_some_and(|parent| matches(parent, left)),
+                Combinator::Descendant => ctx.ancestors().any(|ancestor| matches(ancestor, left)),
+                Combinator::NextSibling => {
+                    ctx.preceding_siblings().next().is_some_and(|sibling| matches(&sibling, left))
+                }
+                Combinator::SubsequentSibling => {
+                    ctx.preceding_siblings().any(|sibling| matches(&sibling, left))
+                }
+            }
+        }
     }
 }
 
-fn matches_simple_selector(elem: &ElementData, selector: &SimpleSelector) -> bool {
+fn matches_simple_selector(ctx: &MatchingContext, selector: &SimpleSelector) -> bool {
+    let elem = ctx.elem();
+
     // Check type selector
     if selector.tag_name.iter().any(|name| elem.tag_name != *name) {
         return false;
@@ -67,49 +222,689 @@ fn matches_simple_selector(elem: &ElementData, selector: &SimpleSelector) -> boo
         return false;
     }
 
+    // Check attribute selectors
+    if selector.attributes.iter().any(|attr| !matches_attribute(elem, attr)) {
+        return false;
+    }
+
+    // Check pseudo-classes
+    if selector.pseudo_classes.iter().any(|pseudo| !matches_pseudo_class(ctx, pseudo)) {
+        return false;
+    }
+
     // We didn't find any non-matching selector components.
     return true;
 }
 
-type MatchedRule<'a> = (Specificity, &'a Rule);
+fn matches_pseudo_class(ctx: &MatchingContext, pseudo: &PseudoClass) -> bool {
+    match *pseudo {
+        PseudoClass::Root => ctx.parent.is_none(),
+        PseudoClass::Empty => ctx.node().children.is_empty(),
+        PseudoClass::FirstChild => ctx.preceding_siblings().next().is_none(),
+        PseudoClass::LastChild => ctx.following_siblings().next().is_none(),
+        PseudoClass::OnlyChild => {
+            ctx.preceding_siblings().next().is_none() && ctx.following_siblings().next().is_none()
+        }
+        PseudoClass::FirstOfType => !ctx.preceding_siblings().any(|s| ctx.same_type(&s)),
+        PseudoClass::LastOfType => !ctx.following_siblings().any(|s| ctx.same_type(&s)),
+        PseudoClass::OnlyOfType => {
+            !ctx.preceding_siblings().any(|s| ctx.same_type(&s)) &&
+                !ctx.following_siblings().any(|s| ctx.same_type(&s))
+        }
+        PseudoClass::NthChild(nth) => nth.matches(ctx.preceding_siblings().count() + 1),
+        PseudoClass::NthLastChild(nth) => nth.matches(ctx.following_siblings().count() + 1),
+        PseudoClass::NthOfType(nth) => {
+            nth.matches(ctx.preceding_siblings().filter(|s| ctx.same_type(s)).count() + 1)
+        }
+        PseudoClass::NthLastOfType(nth) => {
+            nth.matches(ctx.following_siblings().filter(|s| ctx.same_type(s)).count() + 1)
+        }
+        PseudoClass::Not(ref selectors) => !selectors.iter().any(|s| matches(ctx, s)),
+        PseudoClass::Is(ref selectors) | PseudoClass::Where(ref selectors) => {
+            selectors.iter().any(|s| matches(ctx, s))
+        }
+    }
+}
+
+fn matches_attribute(elem: &ElementData, selector: &AttrSelector) -> bool {
+    let value = match elem.attributes.get(&selector.name) {
+        Some(value) => value,
+        None => return false
+    };
+    let fold = |s: &str| if selector.case_insensitive {
+        s.to_ascii_lowercase()
+    } else {
+        s.to_string()
+    };
+    let value = fold(value);
+
+    match selector.operator {
+        AttrOperator::Exists => true,
+        AttrOperator::Equals(ref s) => value == fold(s),
+        AttrOperator::Includes(ref s) => {
+            let s = fold(s);
+            !s.is_empty() && !s.contains(char::is_whitespace) &&
+                value.split_whitespace().any(|word| word == s)
+        }
+        AttrOperator::DashMatch(ref s) => {
+            let s = fold(s);
+            value == s || value.starts_with(&(s + "-"))
+        }
+        // The substring operators never match an empty string.
+        AttrOperator::Prefix(ref s) => !s.is_empty() && value.starts_with(&fold(s)),
+        AttrOperator::Suffix(ref s) => !s.is_empty() && value.ends_with(&fold(s)),
+        AttrOperator::Substring(ref s) => !s.is_empty() && value.contains(&fold(s))
+    }
+}
+
+// A rule that applies to the device being styled for, with its position in the cascade.
+struct CascadeRule<'a> {
+    origin: Origin,
+    sheet: usize,    // index of the stylesheet in the cascade
+    position: usize, // index of the rule within its stylesheet
+    rule: &'a Rule,
+}
+
+// A rule that matched an element, with the specificity of its matching selector.
+#[derive(Clone, Copy, PartialEq)]
+struct MatchedRule {
+    rule: usize, // index of the rule in `Cascade::rules`
+    specificity: Specificity,
+}
+
+// The rules that apply to the device being styled for, with their selectors bucketed for
+// matching.
+struct Cascade<'a> {
+    rules: Vec<CascadeRule<'a>>,
+    selectors: SelectorMap<'a>,
+}
+
+impl<'a> Cascade<'a> {
+    // Collect the rules of all stylesheets whose media queries match the device.
+    fn new(stylesheets: &'a [Stylesheet], device: &Device) -> Cascade<'a> {
+        let mut rules = Vec::new();
+        let mut selectors = SelectorMap::new();
+        for (sheet, stylesheet) in stylesheets.iter().enumerate() {
+            for (position, rule) in stylesheet.rules.iter().enumerate() {
+                if rule.media.iter().all(|media| media.matches(device)) {
+                    for selector in &rule.selectors {
+                        selectors.insert(rules.len(), selector);
+                    }
+                    rules.push(CascadeRule {
+                        origin: stylesheet.origin,
+                        sheet,
+                        position,
+                        rule,
+                    });
+                }
+            }
+        }
+        Cascade { rules, selectors }
+    }
+}
+
+// Find all CSS rules that match the given element, or its pseudo-element `pseudo`.
+//
+// Only selectors keyed on the element's id, classes or tag name, or on none of them, are
+// tested, and `filter` rules out those needing an ancestor the element doesn't have.
+fn matching_rules(ctx: &MatchingContext, cascade: &Cascade, filter: &AncestorFilter,
+                  pseudo: Option<PseudoElement>) -> Vec<MatchedRule> {
+    let mut matched: Vec<(usize, Specificity)> = Vec::new();
+    cascade.selectors.for_each_candidate(ctx.elem(), filter, |entry| {
+        if entry.pseudo_element == pseudo && matches(ctx, entry.selector) {
+            matched.push((entry.rule, entry.specificity));
+        }
+    });
+
+    // A rule applies with the highest specificity of its matching selectors.
+    matched.sort_by(|a, b| a.0.cmp(&b.0).then(b.1.cmp(&a.1)));
+    matched.dedup_by_key(|&mut (rule, _)| rule);
+    matched.into_iter().map(|(rule, specificity)| MatchedRule {
+        specificity,
+        rule,
+    }).collect()
+}
+
+// Find the same rules as `matching_rules` by testing every selector in the cascade against the
+// element, the way matching worked before selectors were bucketed.
+fn matching_rules_linear(ctx: &MatchingContext, cascade: &Cascade,
+                         pseudo: Option<PseudoElement>) -> Vec<MatchedRule> {
+    cascade.rules.iter().enumerate().filter_map(|(rule, cascade_rule)| {
+        cascade_rule.rule.selectors.iter()
+            .filter(|selector| pseudo_element(selector) == pseudo && matches(ctx, selector))
+            .map(|selector| selector.specificity())
+            .max()
+            .map(|specificity| MatchedRule { rule, specificity })
+    }).collect()
+}
+
+fn pseudo_element(selector: &Selector) -> Option<PseudoElement> {
+    match *selector {
+        Selector::Simple(ref simple) | Selector::Complex(_, _, ref simple) => simple.pseudo_element
+    }
+}
+
+/// How `matched_rules` finds the selectors that match an element.
+#[derive(Clone, Copy)]
+pub enum Matching {
+    /// Test every selector in the cascade.
+    Linear,
+    /// Test only the selectors keyed on the element's id, classes or tag name, skipping those
+    /// whose ancestors the ancestor filter rules out. This is how `style_tree` matches.
+    Indexed,
+}
 
-// If `rule` matches `elem`, return a `MatchedRule`. Otherwise return `None`.
-fn match_rule<'a>(elem: &ElementData, rule: &'a Rule) -> Option<MatchedRule<'a>> {
-    // Find the first (highest-specificity) matching selector.
-    rule.selectors.iter()
-        .find(|selector| matches(elem, *selector))
-        .map(|selector| (selector.specificity(), rule))
+/// The rules matching each element of the tree and its `::before` and `::after`, in document
+/// order, as indices into the rules that apply to `device` with their specificities.
+///
+/// Both kinds of matching give the same result; this is for checking and timing that.
+pub fn matched_rules(root: &Node, stylesheets: &[Stylesheet], device: &Device,
+                     matching: Matching) -> Vec<Vec<(usize, Specificity)>> {
+    let cascade = Cascade::new(stylesheets, device);
+    let mut filter = AncestorFilter::new();
+    let mut matched = Vec::new();
+    collect_matched_rules(::std::slice::from_ref(root), 0, None, &cascade, matching, &mut filter,
+                          &mut matched);
+    matched
 }
 
-// Find all CSS rules that match the given element.
-fn matching_rules<'a>(elem: &ElementData, stylesheet: &'a Stylesheet) -> Vec<MatchedRule<'a>> {
-    stylesheet.rules.iter().filter_map(|rule| match_rule(elem, rule)).collect()
+fn collect_matched_rules<'a>(siblings: &'a [Node], index: usize,
+                             parent: Option<&MatchingContext<'a, '_>>, cascade: &Cascade,
+                             matching: Matching, filter: &mut AncestorFilter,
+                             matched: &mut Vec<Vec<(usize, Specificity)>>) {
+    let ctx = MatchingContext { siblings, index, parent };
+    let elem = match ctx.node().node_type {
+        NodeType::Element(ref elem) => elem,
+        NodeType::Text(_) => return
+    };
+    for &pseudo in &[None, Some(PseudoElement::Before), Some(PseudoElement::After)] {
+        let rules = match matching {
+            Matching::Linear => matching_rules_linear(&ctx, cascade, pseudo),
+            Matching::Indexed => matching_rules(&ctx, cascade, filter, pseudo),
+        };
+        matched.push(rules.iter().map(|matched| (matched.rule, matched.specificity)).collect());
+    }
+
+    filter.push(elem);
+    for i in 0..ctx.node().children.len() {
+        collect_matched_rules(&ctx.node().children, i, Some(&ctx), cascade, matching, filter,
+                              matched);
+    }
+    filter.pop(elem);
+}
+
+// The precedence of a declaration's origin and importance; higher levels win.
+//
+// http://www.w3.org/TR/css-cascade-3/#cascade-origin
+fn cascade_level(origin: Origin, important: bool) -> u8 {
+    match (origin, important) {
+        (Origin::UserAgent, false) => 0,
+        (Origin::User, false) => 1,
+        (Origin::Author, false) => 2,
+        (Origin::Author, true) => 3,
+        (Origin::User, true) => 4,
+        (Origin::UserAgent, true) => 5,
+    }
 }
 
-// Apply styles to a single element, returning the specified values.
-fn specified_values(elem: &ElementData, stylesheet: &Stylesheet) -> PropertyMap {
+// Apply the rules that matched an element, returning its specified values.
+fn specified_values(rules: &[MatchedRule], cascade: &Cascade) -> PropertyMap {
     let mut values = HashMap::new();
-    let mut rules = matching_rules(elem, stylesheet);
 
-    // Go through the rules from lowest to highest specificity.
-    rules.sort_by(|&(a, _), &(b, _)| a.cmp(&b));
-    for (_, rule) in rules {
-        for declaration in &rule.declarations {
-            values.insert(declaration.name.clone(), declaration.value.clone());
+    let mut declarations = Vec::new();
+    for matched in rules {
+        let rule = &cascade.rules[matched.rule];
+        for declaration in &rule.rule.declarations {
+            let level = cascade_level(rule.origin, declaration.important);
+            let order = (level, matched.specificity, rule.sheet, rule.position);
+            declarations.push((order, declaration));
         }
     }
+
+    // Apply the declarations from lowest to highest precedence: by origin and importance, then
+    // specificity, then source order. The sort is stable, so declarations within one rule keep
+    // their order.
+    declarations.sort_by_key(|&(order, _)| order);
+    for (_, declaration) in declarations {
+        values.insert(declaration.name.clone(), declaration.value.clone());
+    }
     return values;
 }
 
-// Apply a stylesheet to an entire DOM tree, returning a StyledNode tree.
-pub fn style_tree<'a>(root: &'a Node, stylesheet: &'a Stylesheet) -> StyledNode<'a> {
-    StyledNode {
-        node: root,
-        specified_values: match root.node_type {
-            NodeType::Element(ref elem) => specified_values(elem, stylesheet),
-            NodeType::Text(_) => HashMap::new()
+// Compute an element's values from its specified values and its parent's computed values.
+//
+// Inherited properties that aren't specified take the parent's value, the `inherit`, `initial`
+// and `unset` keywords are resolved, and font- and viewport-relative lengths are converted to px.
+// `font-size` itself is relative to the parent's font size; every other length is relative to
+// the element's own. Returns the values along with the length context for the children.
+fn computed_values(specified: PropertyMap, parent: Option<&ComputedStyle>,
+                   parent_lengths: LengthContext) -> (ComputedStyle, LengthContext) {
+    let parent_value = |name: &str| parent.and_then(|style| style.get(name)).cloned();
+    let mut values: PropertyMap = match parent {
+        Some(parent_style) => parent_style.inherited.values().into_iter()
+            .map(|(name, value)| (name.to_string(), value.clone()))
+            .collect(),
+        None => HashMap::new()
+    };
+
+    // Resolve the CSS-wide keywords, returning `None` if the property ends up with no value.
+    let resolve_keyword = |name: &str, value: Value| match value {
+        Value::Keyword(ref keyword) if keyword == "inherit" => {
+            parent_value(name).or_else(|| properties::initial_value(name))
+        }
+        Value::Keyword(ref keyword) if keyword == "initial" => {
+            properties::initial_value(name)
+        }
+        Value::Keyword(ref keyword) if keyword == "unset" => {
+            if properties::is_inherited(name) {
+                parent_value(name).or_else(|| properties::initial_value(name))
+            } else {
+                properties::initial_value(name)
+            }
+        }
+        value => Some(value)
+    };
+
+    for (name, value) in specified {
+        match resolve_keyword(&name, value) {
+            Some(value) => { values.insert(name, value); }
+            None => { values.remove(&name); }
+        }
+    }
+
+    // Substitute `var()` references, in custom properties first and then in other properties.
+    // A value that is invalid after substitution behaves as `unset`.
+    variables::resolve_custom_properties(&mut values);
+    let pending: Vec<String> = values.iter()
+        .filter(|&(name, value)| match *value {
+            Value::Unparsed(..) => !name.starts_with("--"),
+            _ => false
+        })
+        .map(|(name, _)| name.clone())
+        .collect();
+    for name in pending {
+        let value = substitute_value(&name, &values[&name], &values)
+            .unwrap_or(Value::Keyword(String::from("unset")));
+        match resolve_keyword(&name, value) {
+            Some(value) => { values.insert(name, value); }
+            None => { values.remove(&name); }
+        }
+    }
+
+    let mut lengths = parent_lengths;
+    if let Some(font_size) = values.get("font-size").cloned() {
+        lengths.font_size = match font_size {
+            Value::Length(..) | Value::Calc(_) => {
+                // Percentages are relative to the parent's font size too.
+                font_size.resolve(&parent_lengths).to_px_of(parent_lengths.font_size)
+            }
+            _ => parent_lengths.font_size
+        };
+        values.insert(String::from("font-size"), Value::Length(lengths.font_size, Unit::Px));
+    }
+    if parent.is_none() {
+        lengths.root_font_size = lengths.font_size;
+    }
+    for value in values.values_mut() {
+        *value = value.resolve(&lengths);
+    }
+
+    // `currentcolor` computes to the element's own `color`.
+    if let Some(color) = values.get("color").cloned()
+                               .or_else(|| properties::initial_value("color")) {
+        let current_color = Value::Keyword(String::from("currentcolor"));
+        for value in values.values_mut() {
+            if *value == current_color {
+                *value = color.clone();
+            }
+        }
+    }
+
+    // Sort the values into their groups. Unsupported properties have nowhere to go.
+    let mut inherited = InheritedStyle::default();
+    let mut reset = ResetStyle::default();
+    for (name, value) in values {
+        if properties::is_inherited(&name) {
+            inherited.set(&name, value);
+        } else {
+            reset.set(&name, value);
+        }
+    }
+    let inherited = match parent {
+        Some(parent_style) if *parent_style.inherited == inherited => {
+            parent_style.inherited.clone()
+        }
+        _ => Arc::new(inherited)
+    };
+    (ComputedStyle { inherited, reset: Arc::new(reset) }, lengths)
+}
+
+// Substitute the `var()` references in a property's unparsed value and parse the result.
+//
+// Returns `None` if the value is invalid at computed-value time.
+fn substitute_value(name: &str, value: &Value, values: &PropertyMap) -> Option<Value> {
+    let (text, shorthand) = match *value {
+        Value::Unparsed(ref text, ref shorthand) => (text, shorthand),
+        _ => return Some(value.clone())
+    };
+    let text = variables::substitute(text, &mut |var| match values.get(var) {
+        Some(&Value::Unparsed(ref text, None)) => Some(text.clone()),
+        _ => None
+    })?;
+
+    // A longhand set through a shorthand takes its part of the expanded shorthand.
+    let expanded_name = shorthand.as_ref().map_or(name, |shorthand| &**shorthand);
+    properties::expand(expanded_name, css::parse_values(text)?, false).into_iter()
+        .find(|declaration| declaration.name == name)
+        .map(|declaration| declaration.value)
+}
+
+// Apply stylesheets to an entire DOM tree, returning a StyledNode tree.
+//
+// Stylesheets later in `stylesheets` win over earlier ones of the same origin and specificity.
+// The device decides which `@media` rules apply, and its viewport is needed to resolve
+// viewport-percentage lengths.
+pub fn style_tree<'a>(root: &'a Node, stylesheets: &'a [Stylesheet], device: &Device)
+                      -> StyledNode<'a> {
+    style_tree_with_stats(root, stylesheets, device, Traversal::Sequential).0
+}
+
+/// How `style_tree_with_stats` walks the DOM.
+#[derive(Clone, Copy)]
+pub enum Traversal {
+    Sequential,
+    /// Style sibling subtrees on up to this many threads at once.
+    Parallel(usize),
+}
+
+/// Like `style_tree`, walking the DOM as `traversal` says and reporting how well the style
+/// sharing cache did. Every traversal produces the same style tree.
+pub fn style_tree_with_stats<'a>(root: &'a Node, stylesheets: &'a [Stylesheet], device: &Device,
+                                 traversal: Traversal) -> (StyledNode<'a>, SharingStats) {
+    let initial = LengthContext {
+        font_size: DEFAULT_FONT_SIZE,
+        root_font_size: DEFAULT_FONT_SIZE,
+        viewport_width: device.viewport.content.width,
+        viewport_height: device.viewport.content.height,
+    };
+    let cascade = Cascade::new(stylesheets, device);
+    let mut state = TraversalState {
+        filter: AncestorFilter::new(),
+        sharing: SharingCache::new(),
+        threads: match traversal {
+            Traversal::Sequential => 1,
+            Traversal::Parallel(threads) => threads,
         },
-        children: root.children.iter().map(|child| style_tree(child, stylesheet)).collect(),
+    };
+    let root = ::std::slice::from_ref(root);
+    let styled = style_node_parallel(root, 0, None, None, &cascade, &mut state, initial);
+    (styled, state.sharing.stats)
+}
+
+// What is carried from node to node while styling a tree. Each thread has its own.
+struct TraversalState {
+    filter: AncestorFilter,
+    sharing: SharingCache,
+    threads: usize, // how many threads may style the current subtree, counting this one
+}
+
+// Compute the style of the node `ctx` refers to, returning it along with the length context for
+// its children.
+fn style_self(ctx: &MatchingContext, parent_values: Option<&Arc<ComputedStyle>>, cascade: &Cascade,
+              state: &mut TraversalState, parent_lengths: LengthContext)
+              -> (Arc<ComputedStyle>, LengthContext) {
+    let rules = match ctx.node().node_type {
+        NodeType::Element(_) => matching_rules(ctx, cascade, &state.filter, None),
+        NodeType::Text(_) => Vec::new()
+    };
+    match state.sharing.lookup(parent_values, &rules) {
+        Some(shared) => shared,
+        None => {
+            let specified = specified_values(&rules, cascade);
+            let (values, lengths) = computed_values(specified, parent_values.map(|v| &**v),
+                                                    parent_lengths);
+            let values = Arc::new(values);
+            state.sharing.insert(parent_values, rules, values.clone(), lengths);
+            (values, lengths)
+        }
+    }
+}
+
+// Style the node at `siblings[index]` and its descendants.
+fn style_node<'a>(siblings: &'a [Node], index: usize, parent: Option<&MatchingContext<'a, '_>>,
+                  parent_values: Option<&Arc<ComputedStyle>>, cascade: &Cascade,
+                  state: &mut TraversalState, parent_lengths: LengthContext) -> StyledNode<'a> {
+    let node = &siblings[index];
+    let ctx = MatchingContext { siblings, index, parent };
+    let (values, lengths) = style_self(&ctx, parent_values, cascade, state, parent_lengths);
+
+    // The filter holds exactly the ancestors of the node being styled.
+    if let NodeType::Element(ref elem) = node.node_type {
+        state.filter.push(elem);
+    }
+    let children = (0..node.children.len())
+        .map(|i| style_node(&node.children, i, Some(&ctx), Some(&values), cascade, state,
+                            lengths))
+        .collect();
+    if let NodeType::Element(ref elem) = node.node_type {
+        state.filter.pop(elem);
+    }
+    styled_node(&ctx, values, lengths, children, cascade, state)
+}
+
+// Put together the styled node for `ctx`, styling its `::before` and `::after` pseudo-elements.
+fn styled_node<'a>(ctx: &MatchingContext<'a, '_>, values: Arc<ComputedStyle>,
+                   lengths: LengthContext, children: Vec<StyledNode<'a>>, cascade: &Cascade,
+                   state: &TraversalState) -> StyledNode<'a> {
+    StyledNode {
+        node: ctx.node(),
+        before: style_pseudo_element(ctx, PseudoElement::Before, &values, lengths, cascade, state),
+        after: style_pseudo_element(ctx, PseudoElement::After, &values, lengths, cascade, state),
+        pseudo: None,
+        computed: values,
+        children,
+    }
+}
+
+// Style a pseudo-element of the element `ctx`. It only exists if its `content` isn't `normal`
+// or `none`.
+fn style_pseudo_element<'a>(ctx: &MatchingContext<'a, '_>, pseudo: PseudoElement,
+                            values: &Arc<ComputedStyle>, lengths: LengthContext,
+                            cascade: &Cascade, state: &TraversalState)
+                            -> Option<Box<StyledNode<'a>>> {
+    if !is_element(ctx.node()) {
+        return None;
+    }
+    let rules = matching_rules(ctx, cascade, &state.filter, Some(pseudo));
+    if rules.is_empty() {
+        return None;
+    }
+    let (style, _) = computed_values(specified_values(&rules, cascade), Some(values), lengths);
+    match style.reset.content {
+        Some(Value::Content(_)) => Some(Box::new(StyledNode {
+            node: ctx.node(),
+            computed: Arc::new(style),
+            children: Vec::new(),
+            pseudo: Some(pseudo),
+            before: None,
+            after: None,
+        })),
+        _ => None
+    }
+}
+
+// The fewest children worth handing out to other threads. Nodes with fewer are styled in turn,
+// each looking for a larger family further down.
+const MIN_PARALLEL_CHILDREN: usize = 4;
+
+// Style the node at `siblings[index]` and its descendants on up to `state.threads` threads,
+// spreading the children of nodes with enough of them over the threads.
+fn style_node_parallel<'a>(siblings: &'a [Node], index: usize,
+                           parent: Option<&MatchingContext<'a, '_>>,
+                           parent_values: Option<&Arc<ComputedStyle>>, cascade: &Cascade,
+                           state: &mut TraversalState, parent_lengths: LengthContext)
+                           -> StyledNode<'a> {
+    if state.threads <= 1 {
+        return style_node(siblings, index, parent, parent_values, cascade, state, parent_lengths);
+    }
+    let node = &siblings[index];
+    let ctx = MatchingContext { siblings, index, parent };
+    let (values, lengths) = style_self(&ctx, parent_values, cascade, state, parent_lengths);
+
+    let children = if node.children.len() >= MIN_PARALLEL_CHILDREN {
+        let (children, stats) = style_children_parallel(&ctx, &values, cascade, lengths,
+                                                        state.threads);
+        state.sharing.stats.hits += stats.hits;
+        state.sharing.stats.misses += stats.misses;
+        children
+    } else {
+        if let NodeType::Element(ref elem) = node.node_type {
+            state.filter.push(elem);
+        }
+        let children = (0..node.children.len())
+            .map(|i| style_node_parallel(&node.children, i, Some(&ctx), Some(&values), cascade,
+                                         state, lengths))
+            .collect();
+        if let NodeType::Element(ref elem) = node.node_type {
+            state.filter.pop(elem);
+        }
+        children
+    };
+    styled_node(&ctx, values, lengths, children, cascade, state)
+}
+
+// Style the children of the element `ctx` on up to `threads` threads. Each thread takes the next
+// unstyled child whenever it finishes one, so large and small subtrees even out, and the results
+// are put back in document order. With fewer children than threads, the threads left over are
+// shared out for styling the children's own children.
+fn style_children_parallel<'a>(ctx: &MatchingContext<'a, '_>, values: &Arc<ComputedStyle>,
+                               cascade: &Cascade, lengths: LengthContext, threads: usize)
+                               -> (Vec<StyledNode<'a>>, SharingStats) {
+    let children = &ctx.node().children;
+    let workers = ::std::cmp::min(threads, children.len());
+    let next = AtomicUsize::new(0);
+    let results: Vec<_> = thread::scope(|scope| {
+        let workers: Vec<_> = (0..workers).map(|_| scope.spawn(|| {
+            let mut state = TraversalState {
+                filter: ancestor_filter(ctx),
+                sharing: SharingCache::new(),
+                threads: threads / workers,
+            };
+            let mut styled = Vec::new();
+            loop {
+                let i = next.fetch_add(1, Ordering::Relaxed);
+                if i >= children.len() {
+                    break;
+                }
+                styled.push((i, style_node_parallel(children, i, Some(ctx), Some(values), cascade,
+                                                    &mut state, lengths)));
+            }
+            (styled, state.sharing.stats)
+        })).collect();
+        workers.into_iter().map(|worker| worker.join().unwrap()).collect()
+    });
+
+    let mut slots: Vec<Option<StyledNode<'a>>> = children.iter().map(|_| None).collect();
+    let mut stats = SharingStats::default();
+    for (styled, worker_stats) in results {
+        for (i, child) in styled {
+            slots[i] = Some(child);
+        }
+        stats.hits += worker_stats.hits;
+        stats.misses += worker_stats.misses;
+    }
+    (slots.into_iter().map(|child| child.unwrap()).collect(), stats)
+}
+
+// A filter holding the element `ctx` and its ancestors, for styling the element's children.
+fn ancestor_filter(ctx: &MatchingContext) -> AncestorFilter {
+    let mut filter = AncestorFilter::new();
+    filter.push(ctx.elem());
+    for ancestor in ctx.ancestors() {
+        filter.push(ancestor.elem());
+    }
+    filter
+}
+
+// How many recently computed styles the style sharing cache keeps.
+const SHARING_CACHE_SIZE: usize = 64;
+
+/// How often the style sharing cache let a node reuse a style computed for an earlier one.
+#[derive(Clone, Copy, Default)]
+pub struct SharingStats {
+    pub hits: usize,
+    pub misses: usize,
+}
+
+impl SharingStats {
+    /// The fraction of nodes whose style was shared, from 0 to 1.
+    pub fn hit_rate(&self) -> f32 {
+        match self.hits + self.misses {
+            0 => 0.0,
+            total => self.hits as f32 / total as f32
+        }
+    }
+}
+
+// A computed style, with the inputs it was computed from.
+struct SharingEntry {
+    parent: Option<Arc<ComputedStyle>>,
+    rules: Vec<MatchedRule>,
+    values: Arc<ComputedStyle>,
+    lengths: LengthContext,
+}
+
+// The styles computed most recently, newest first.
+//
+// A node's computed values depend only on the rules it matched and on its parent's computed
+// values, so a node that matched the same rules as a cached one, under a parent with the very same
+// style, can share that style. Siblings always have the same parent style, and cousins do when
+// their parents shared theirs.
+struct SharingCache {
+    entries: VecDeque<SharingEntry>,
+    stats: SharingStats,
+}
+
+impl SharingCache {
+    fn new() -> SharingCache {
+        SharingCache { entries: VecDeque::new(), stats: Default::default() }
+    }
+
+    fn lookup(&mut self, parent: Option<&Arc<ComputedStyle>>, rules: &[MatchedRule])
+              -> Option<(Arc<ComputedStyle>, LengthContext)> {
+        let position = self.entries.iter().position(|entry| {
+            let same_parent = match (entry.parent.as_ref(), parent) {
+                (Some(a), Some(b)) => Arc::ptr_eq(a, b),
+                (None, None) => true,
+                _ => false
+            };
+            same_parent && &*entry.rules == rules
+        });
+        match position {
+            Some(i) => {
+                self.stats.hits += 1;
+                // Keep the entry at the front, so the most useful ones stay cached.
+                let entry = self.entries.remove(i).unwrap();
+                let shared = (entry.values.clone(), entry.lengths);
+                self.entries.push_front(entry);
+                Some(shared)
+            }
+            None => {
+                self.stats.misses += 1;
+                None
+            }
+        }
+    }
+
+    fn insert(&mut self, parent: Option<&Arc<ComputedStyle>>, rules: Vec<MatchedRule>,
+              values: Arc<ComputedStyle>, lengths: LengthContext) {
+        self.entries.truncate(SHARING_CACHE_SIZE - 1);
+        self.entries.push_front(SharingEntry {
+            parent: parent.cloned(),
+            rules,
+            values,
+            lengths,
+        });
     }
 }
 
@@ -132,27 +927,48 @@ pub fn print(root_node: StyledNode) {
             print!(" ");
         }
 
-        match current.stnode.node.node_type {
-            NodeType::Element(ref e) => { print!("elem: {} ", e.tag_name) },
-            NodeType::Text(ref t) => { print!("txt: {} ", t) }
+        match (current.stnode.pseudo, &current.stnode.node.node_type) {
+            (Some(PseudoElement::Before), _) => { print!("::before ") },
+            (Some(PseudoElement::After), _) => { print!("::after ") },
+            (None, NodeType::Element(e)) => { print!("elem: {} ", e.tag_name) },
+            (None, NodeType::Text(t)) => { print!("txt: {} ", t) }
         }
 
-        for (s, v) in current.stnode.specified_values.iter() {
+        for (s, v) in current.stnode.computed.values() {
             print!(".{}=", s);
             match *v {
-                Value::Keyword(_) => ( print!("? ")),
-                Value::Length(l, _) => (print!("{}px ", l)),
+                Value::Keyword(_) => print!("? "),
+                Value::Length(l, Unit::Percent) => print!("{}% ", l),
+                Value::Length(l, Unit::Fr) => print!("{}fr ", l),
+                Value::Length(l, _) => print!("{}px ", l),
+                Value::Number(n) => print!("{} ", n),
+                Value::Unparsed(ref text, _) => print!("{} ", text),
+                Value::Calc(_) => print!("calc() "),
+                Value::Str(ref text) => print!("\"{}\" ", text),
+                Value::Content(_) => print!("content() "),
+                Value::Tracks(_) => print!("tracks() "),
+                Value::Areas(ref rows) => print!("{} rows of areas ", rows.len()),
+                Value::Span(n) => print!("span {} ", n),
+                Value::Pair(..) => print!("pair() "),
+                Value::Transform(ref functions) => print!("{} ", functions.join(" ")),
+                Value::Counters(ref counters) => {
+                    for &(ref name, n) in counters {
+                        print!("{}:{} ", name, n);
+                    }
+                }
                 Value::ColorValue(ref c) => {
                     print!("{}r-{}g-{}b ", c.r, c.g, c.b)
                 }
             }
         }
 
-        // Add the children to the stack to traverse the tree
+        // Add the children to the stack to traverse the tree, with the pseudo-elements around them
         let mut rev_child: Vec<StyledNode> = Vec::new();
+        rev_child.extend(current.stnode.before.map(|before| *before));
         for child in current.stnode.children {
           rev_child.push(child);
         }
+        rev_child.extend(current.stnode.after.map(|after| *after));
         rev_child.reverse();
 
         for child in rev_child {
@@ -166,3 +982,156 @@ pub fn print(root_node: StyledNode) {
 
     println!("");
 }
+
+/// Print the hit rate of the style sharing cache.
+pub fn print_sharing(stats: &SharingStats) {
+    println!("Style sharing: {} of {} nodes shared a style ({:.1}%)", stats.hits,
+             stats.hits + stats.misses, stats.hit_rate() * 100.0);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use html;
+    use media::MediaType;
+
+    // The computed value of `name` on the root element of `source`, styled by `sheets`.
+    fn computed(source: &str, sheets: &[(&str, Origin)], name: &str) -> Option<Value> {
+        let root = html::parse(String::from(source));
+        let sheets: Vec<Stylesheet> = sheets.iter()
+            .map(|&(css, origin)| css::parse(String::from(css), origin))
+            .collect();
+        let device = Device::new(MediaType::Screen, Default::default());
+        style_tree(&root, &sheets, &device).value(name)
+    }
+
+    fn px(n: f32) -> Option<Value> {
+        Some(Value::Length(n, Unit::Px))
+    }
+
+    // A document with enough siblings at each level to be styled in parallel, and selectors
+    // reaching across ancestors and siblings.
+    const FIXTURE: &str = "<div class=\"report\">\
+        <section id=\"s1\" class=\"a\"><p class=\"x y\">1</p><p>2</p><span class=\"x\">3</span>\
+            <p id=\"q\">4</p><p>5</p></section>\
+        <section id=\"s2\"><ul><li class=\"item\"><a href=\"#\">6</a></li><li>7</li>\
+            <li class=\"item\"><a>8</a></li><li>9</li></ul></section>\
+        <section class=\"a\"><p>10</p><p class=\"x\">11</p></section>\
+        <div><div></div></div></div>";
+
+    const FIXTURE_CSS: &str = "* { display: block; } .report .x { width: 1px; } \
+        section > p + p { width: 2px; } #s1 ~ section li { height: 3px; } \
+        li.item:nth-child(odd) a { color: #ff0000; } p.x.y, #q { margin-top: 4px; } \
+        a[href] { margin-left: 5px; } section p::before { content: \"-\"; } \
+        .a > p:last-child::after { content: \"+\"; } div div { height: 1px; }";
+
+    fn fixture() -> (Node, Vec<Stylesheet>, Device) {
+        let root = html::parse(String::from(FIXTURE));
+        let sheets = vec![css::user_agent(),
+                          css::parse(String::from(FIXTURE_CSS), Origin::Author)];
+        (root, sheets, Device::new(MediaType::Screen, Default::default()))
+    }
+
+    #[test]
+    fn parallel_traversal_matches_sequential() {
+        let (root, sheets, device) = fixture();
+        let (sequential, stats) = style_tree_with_stats(&root, &sheets, &device,
+                                                        Traversal::Sequential);
+        // With 8 or more threads, the sections' children are styled in parallel too.
+        for &threads in &[2, 3, 4, 8, 16] {
+            let (parallel, parallel_stats) = style_tree_with_stats(&root, &sheets, &device,
+                                                                   Traversal::Parallel(threads));
+            assert!(parallel == sequential);
+            assert_eq!(parallel_stats.hits + parallel_stats.misses, stats.hits + stats.misses);
+        }
+    }
+
+    #[test]
+    fn indexed_matching_finds_the_same_rules_as_linear_matching() {
+        let (root, sheets, device) = fixture();
+        let linear = matched_rules(&root, &sheets, &device, Matching::Linear);
+        assert!(linear.iter().filter(|rules| rules.len() > 1).count() > 10);
+        assert!(matched_rules(&root, &sheets, &device, Matching::Indexed) == linear);
+    }
+
+    #[test]
+    #[cfg_attr(debug_assertions, should_panic(expected = "Unsupported property colour"))]
+    fn unsupported_properties_have_no_value() {
+        let sheets = [("p { color: #ff0000; }", Origin::Author)];
+        assert!(computed("<p></p>", &sheets, "colour").is_none());
+    }
+
+    #[test]
+    fn var_substitution() {
+        let sheets = [(":root { --w: 10px; --m: 1px 2px; --c: #ff0000; } \
+                        p { width: var(--w); margin: var(--m); \
+                            border-color: var(--c, #00ff00) var(--nope, #0000ff); \
+                            height: calc(var(--w) * 2); }", Origin::Author)];
+        let value = |name| computed("<p></p>", &sheets, name);
+        assert!(value("width") == px(10.0));
+        assert!(value("margin-top") == px(1.0));
+        assert!(value("margin-left") == px(2.0));
+        assert!(value("border-top-color") == css::parse_value(String::from("#ff0000")));
+        assert!(value("border-right-color") == css::parse_value(String::from("#0000ff")));
+        assert!(value("height") == px(20.0));
+        assert!(value("--m") == Some(Value::Unparsed(String::from("1px 2px"), None)));
+    }
+
+    #[test]
+    fn invalid_var_references_unset_the_property() {
+        let sheets = [("p { width: 5px; color: #ff0000; } \
+                        p { --a: var(--b); --b: var(--a); width: var(--missing); \
+                            color: var(--a); }", Origin::Author)];
+        assert!(computed("<p></p>", &sheets, "width") == properties::initial_value("width"));
+        assert!(computed("<p></p>", &sheets, "--a").is_none());
+        assert!(computed("<p></p>", &sheets, "color") == properties::initial_value("color"));
+    }
+
+    #[test]
+    fn custom_properties_inherit() {
+        let root = html::parse(String::from("<div><p></p></div>"));
+        let sheets = [css::parse(String::from("div { --w: 3px; } p { width: var(--w); }"),
+                                 Origin::Author)];
+        let device = Device::new(MediaType::Screen, Default::default());
+        let styled = style_tree(&root, &sheets, &device);
+        assert!(styled.children[0].value("width") == px(3.0));
+    }
+
+    #[test]
+    fn specificity_beats_source_order() {
+        let sheets = [("#a { width: 1px; } p.c { width: 2px; } p { width: 3px; }", Origin::Author)];
+        assert!(computed("<p id=\"a\" class=\"c\"></p>", &sheets, "width") == px(1.0));
+        assert!(computed("<p class=\"c\"></p>", &sheets, "width") == px(2.0));
+    }
+
+    #[test]
+    fn later_declarations_win() {
+        let sheets = [("p { width: 1px; width: 2px; } p { height: 1px; } p { height: 2px; }",
+                       Origin::Author),
+                      ("p { margin-top: 1px; }", Origin::Author),
+                      ("p { margin-top: 2px; }", Origin::Author)];
+        assert!(computed("<p></p>", &sheets, "width") == px(2.0));
+        assert!(computed("<p></p>", &sheets, "height") == px(2.0));
+        assert!(computed("<p></p>", &sheets, "margin-top") == px(2.0));
+    }
+
+    #[test]
+    fn origins_beat_specificity() {
+        let sheets = [("#a { width: 1px; }", Origin::UserAgent),
+                      ("p#a { width: 2px; }", Origin::User),
+                      ("p { width: 3px; }", Origin::Author)];
+        assert!(computed("<p id=\"a\"></p>", &sheets, "width") == px(3.0));
+    }
+
+    #[test]
+    fn important_declarations_reverse_the_origins() {
+        let sheets = [("p { width: 1px !important; height: 1px; }", Origin::UserAgent),
+                      ("p { width: 2px !important; height: 2px !important; }", Origin::User),
+                      ("#a { width: 3px; height: 3px !important; margin-top: 3px; } \
+                        p { margin-top: 4px !important; }", Origin::Author)];
+        let source = "<p id=\"a\"></p>";
+        assert!(computed(source, &sheets, "width") == px(1.0));
+        assert!(computed(source, &sheets, "height") == px(2.0));
+        assert!(computed(source, &sheets, "margin-top") == px(4.0));
+    }
+}
@@ -0,0 +1,187 @@
+// Custom properties and `var()` substitution.
+//
+// http://www.w3.org/TR/css-variables-1/
+
+use css::Value;
+use std::collections::{HashMap, HashSet};
+
+/// Substitute the `var()` references in the custom properties among `values`.
+///
+/// Custom properties that are part of a reference cycle, or that refer to a missing property
+/// without a fallback, are invalid at computed-value time and are removed.
+pub fn resolve_custom_properties(values: &mut HashMap<String, Value>) {
+    let names: Vec<String> = values.keys().filter(|name| name.starts_with("--")).cloned().collect();
+    let resolved = {
+        let mut resolver = Resolver {
+            values,
+            resolved: HashMap::new(),
+            stack: Vec::new(),
+            in_cycle: HashSet::new(),
+        };
+        for name in &names {
+            resolver.resolve(name);
+        }
+        resolver.resolved
+    };
+
+    for (name, text) in resolved {
+        match text {
+            Some(text) => { values.insert(name, Value::Unparsed(text, None)); }
+            None => { values.remove(&name); }
+        }
+    }
+}
+
+// Resolves custom properties depth-first, detecting reference cycles along the way.
+struct Resolver<'a> {
+    values: &'a HashMap<String, Value>,
+    resolved: HashMap<String, Option<String>>,
+    stack: Vec<String>, // properties being resolved, outermost first
+    in_cycle: HashSet<String>,
+}
+
+impl<'a> Resolver<'a> {
+    fn resolve(&mut self, name: &str) -> Option<String> {
+        if let Some(text) = self.resolved.get(name) {
+            return text.clone();
+        }
+        if let Some(start) = self.stack.iter().position(|n| n == name) {
+            // Every property in the cycle is invalid, whatever fallbacks it has.
+            for n in &self.stack[start..] {
+                self.in_cycle.insert(n.clone());
+            }
+            return None;
+        }
+        let text = match self.values.get(name) {
+            Some(&Value::Unparsed(ref text, None)) => text.clone(),
+            _ => return None
+        };
+
+        self.stack.push(name.to_string());
+        let result = substitute(&text, &mut |var| self.resolve(var));
+        self.stack.pop();
+
+        let result = if self.in_cycle.contains(name) { None } else { result };
+        self.resolved.insert(name.to_string(), result.clone());
+        result
+    }
+}
+
+/// Replace every `var(--name, fallback)` in `text` with the value `lookup` returns for the
+/// name, or with its fallback. Returns `None` if a reference can't be resolved.
+pub fn substitute(text: &str, lookup: &mut dyn FnMut(&str) -> Option<String>) -> Option<String> {
+    let mut result = String::new();
+    let mut rest = text;
+    while let Some(start) = find_var(rest) {
+        result.push_str(&rest[..start]);
+        let args_start = start + "var(".len();
+        let args_end = args_start + matching_paren(&rest[args_start..])?;
+        let args = &rest[args_start..args_end];
+
+        let (name, fallback) = match args.find(',') {
+            Some(comma) => (args[..comma].trim(), Some(args[comma + 1..].trim())),
+            None => (args.trim(), None)
+        };
+        let value = match lookup(name) {
+            Some(value) => value,
+            None => substitute(fallback?, lookup)?
+        };
+        result.push_str(&value);
+        rest = &rest[args_end + 1..];
+    }
+    result.push_str(rest);
+    Some(result)
+}
+
+// Find the start of the next `var(` in `text`, ignoring case.
+fn find_var(text: &str) -> Option<usize> {
+    text.to_ascii_lowercase().find("var(")
+}
+
+// Find the `)` that closes a parenthesized group whose contents start `text`.
+fn matching_paren(text: &str) -> Option<usize> {
+    let mut depth = 0;
+    for (i, c) in text.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' if depth == 0 => return Some(i),
+            ')' => depth -= 1,
+            _ => {}
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn unparsed(text: &str) -> Value {
+        Value::Unparsed(String::from(text), None)
+    }
+
+    // Resolve the custom properties `declared` as `(name, text)`, returning the text of each
+    // that's still valid.
+    fn resolve(declared: &[(&str, &str)]) -> HashMap<String, String> {
+        let mut values: HashMap<String, Value> = declared.iter()
+            .map(|&(name, text)| (String::from(name), unparsed(text)))
+            .collect();
+        resolve_custom_properties(&mut values);
+        values.into_iter()
+            .map(|(name, value)| match value {
+                Value::Unparsed(text, None) => (name, text),
+                _ => panic!("{} isn't unparsed", name)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn substitution() {
+        let lookup = &mut |name: &str| match name {
+            "--a" => Some(String::from("1px")),
+            "--b" => Some(String::from("solid")),
+            _ => None
+        };
+        assert_eq!(substitute("var(--a) VAR(--b) red", lookup),
+                   Some(String::from("1px solid red")));
+        assert_eq!(substitute("calc(var(--a) * 2)", lookup), Some(String::from("calc(1px * 2)")));
+        assert_eq!(substitute("var(--c, 2px)", lookup), Some(String::from("2px")));
+        assert_eq!(substitute("var(--c, var(--d, calc(1px + 2px)))", lookup),
+                   Some(String::from("calc(1px + 2px)")));
+        assert_eq!(substitute("var(--c, var(--a), 3px)", lookup), Some(String::from("1px, 3px")));
+        assert_eq!(substitute("var(--c)", lookup), None);
+        assert_eq!(substitute("var(--a", lookup), None);
+        assert_eq!(substitute("no references", lookup), Some(String::from("no references")));
+    }
+
+    #[test]
+    fn chained_properties() {
+        let resolved = resolve(&[("--a", "var(--b) var(--c)"), ("--b", "var(--c)"), ("--c", "1px"),
+                                 ("--d", "var(--missing, var(--a))")]);
+        assert_eq!(resolved["--a"], "1px 1px");
+        assert_eq!(resolved["--b"], "1px");
+        assert_eq!(resolved["--d"], "1px 1px");
+    }
+
+    #[test]
+    fn missing_references_are_invalid() {
+        let resolved = resolve(&[("--a", "var(--missing)"), ("--b", "var(--a, 2px)"),
+                                 ("--c", "var(--a)")]);
+        assert!(!resolved.contains_key("--a"));
+        assert_eq!(resolved["--b"], "2px");
+        assert!(!resolved.contains_key("--c"));
+    }
+
+    #[test]
+    fn cycles_are_invalid() {
+        let resolved = resolve(&[("--a", "var(--b)"), ("--b", "var(--c, 1px)"),
+                                 ("--c", "var(--a)"), ("--self", "var(--self, 1px)"),
+                                 ("--outside", "var(--a, 2px)"), ("--fine", "3px")]);
+        // Fallbacks don't rescue properties in the cycle, but do rescue those referring to it.
+        for name in &["--a", "--b", "--c", "--self"] {
+            assert!(!resolved.contains_key(*name), "{} is in a cycle", name);
+        }
+        assert_eq!(resolved["--outside"], "2px");
+        assert_eq!(resolved["--fine"], "3px");
+    }
+}